@@ -0,0 +1,288 @@
+//! Messages published while disconnected, replayed in order once the broker is back
+//!
+//! Messages are kept in memory. When that is full they spill over to a JSON
+//! lines file, if configured, and once that is full as well new messages are
+//! dropped.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Result, config::BufferConfig};
+
+/// Message waiting to be published
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Buffered {
+    /// Topic relative to the base topic
+    pub topic: String,
+    pub payload: String,
+    pub qos: u8,
+    pub retain: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_expiry: Option<u32>,
+    /// When the message was originally published
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Bounded queue of messages, in memory with spill over to disk
+pub struct Buffer {
+    online: bool,
+    max_memory: usize,
+    max_disk: usize,
+    path: Option<PathBuf>,
+    memory: VecDeque<Buffered>,
+    /// Messages in the spill file, which are older than those in memory
+    on_disk: usize,
+    dropped: u64,
+}
+
+impl Buffer {
+    /// Create buffer, picking up messages left in the spill file by a previous run
+    pub fn new(config: &BufferConfig) -> Self {
+        let on_disk = match &config.path {
+            Some(path) => match fs::File::open(path) {
+                Ok(file) => BufReader::new(file).lines().count(),
+                Err(_) => 0,
+            },
+            None => 0,
+        };
+        Self {
+            online: false,
+            max_memory: config.max_memory,
+            max_disk: config.max_disk,
+            path: config.path.clone(),
+            memory: VecDeque::new(),
+            on_disk,
+            dropped: 0,
+        }
+    }
+
+    /// Whether messages can be published right away
+    pub fn is_online(&self) -> bool {
+        self.online
+    }
+
+    /// Keep messages from now on, until replayed
+    pub fn set_offline(&mut self) {
+        self.online = false;
+    }
+
+    /// Keep message until the broker is back
+    pub fn push(&mut self, message: Buffered) {
+        if self.memory.len() >= self.max_memory
+            && let Err(e) = self.spill()
+        {
+            eprintln!("buffer_spill_error={e}");
+        }
+        if self.memory.len() < self.max_memory {
+            self.memory.push_back(message);
+            return;
+        }
+        self.dropped += 1;
+        if self.dropped == 1 {
+            eprintln!("buffer_full, dropping messages until reconnected");
+        }
+    }
+
+    /// Oldest messages to replay, marks the buffer online once it is empty
+    pub fn take_batch(&mut self) -> Vec<Buffered> {
+        if self.on_disk > 0 {
+            match self.read_spilled() {
+                Ok(messages) => return messages,
+                Err(e) => eprintln!("buffer_read_error={e}"),
+            }
+        }
+        if self.memory.is_empty() {
+            if self.dropped > 0 {
+                eprintln!("buffer_dropped={}", self.dropped);
+                self.dropped = 0;
+            }
+            self.online = true;
+        }
+        self.memory.drain(..).collect()
+    }
+
+    /// Move all messages in memory to the end of the spill file, if there is room
+    fn spill(&mut self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.on_disk + self.memory.len() > self.max_disk {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut lines = Vec::new();
+        for message in &self.memory {
+            serde_json::to_writer(&mut lines, message)?;
+            lines.push(b'\n');
+        }
+        file.write_all(&lines)?;
+        self.on_disk += self.memory.len();
+        self.memory.clear();
+        Ok(())
+    }
+
+    /// Read and remove the spill file
+    fn read_spilled(&mut self) -> Result<Vec<Buffered>> {
+        self.on_disk = 0;
+        let Some(path) = &self.path else {
+            return Ok(Vec::new());
+        };
+        let contents = fs::read_to_string(path)?;
+        fs::remove_file(path)?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(message) => Some(message),
+                Err(e) => {
+                    eprintln!("buffer_parse_error={e}");
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn message(topic: &str) -> Buffered {
+        Buffered {
+            topic: topic.into(),
+            payload: "1".into(),
+            qos: 1,
+            retain: true,
+            content_type: None,
+            message_expiry: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn config(max_memory: usize, max_disk: usize, path: Option<&Path>) -> BufferConfig {
+        BufferConfig {
+            enabled: Some(true),
+            max_memory,
+            max_disk,
+            path: path.map(Path::to_path_buf),
+        }
+    }
+
+    /// Spill file unique to the test, removed when dropped
+    struct SpillFile(PathBuf);
+
+    impl SpillFile {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("modo-test-{}-{name}.jsonl", std::process::id()));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for SpillFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn topics(batch: Vec<Buffered>) -> Vec<String> {
+        batch.into_iter().map(|message| message.topic).collect()
+    }
+
+    #[test]
+    fn drops_when_memory_is_full_without_spill_file() {
+        let mut buffer = Buffer::new(&config(2, 10, None));
+        for topic in ["a", "b", "c"] {
+            buffer.push(message(topic));
+        }
+        assert_eq!(buffer.dropped, 1);
+        assert_eq!(topics(buffer.take_batch()), ["a", "b"]);
+        assert!(buffer.take_batch().is_empty());
+        assert!(buffer.is_online());
+    }
+
+    #[test]
+    fn spills_when_memory_is_full() {
+        let file = SpillFile::new("spill");
+        let mut buffer = Buffer::new(&config(2, 10, Some(&file.0)));
+        for topic in ["a", "b", "c"] {
+            buffer.push(message(topic));
+        }
+        assert_eq!(buffer.on_disk, 2);
+        assert_eq!(buffer.memory.len(), 1);
+        assert!(file.0.is_file());
+    }
+
+    #[test]
+    fn replays_spilled_messages_before_memory() {
+        let file = SpillFile::new("order");
+        let mut buffer = Buffer::new(&config(2, 10, Some(&file.0)));
+        for topic in ["a", "b", "c", "d", "e"] {
+            buffer.push(message(topic));
+        }
+        assert_eq!(topics(buffer.take_batch()), ["a", "b", "c", "d"]);
+        assert!(!buffer.is_online());
+        assert!(!file.0.exists());
+        assert_eq!(topics(buffer.take_batch()), ["e"]);
+        assert!(!buffer.is_online());
+        assert!(buffer.take_batch().is_empty());
+        assert!(buffer.is_online());
+    }
+
+    #[test]
+    fn drops_when_spill_file_is_full() {
+        let file = SpillFile::new("full");
+        let mut buffer = Buffer::new(&config(2, 2, Some(&file.0)));
+        for topic in ["a", "b", "c", "d", "e"] {
+            buffer.push(message(topic));
+        }
+        assert_eq!(buffer.dropped, 1);
+        assert_eq!(topics(buffer.take_batch()), ["a", "b"]);
+        assert_eq!(topics(buffer.take_batch()), ["c", "d"]);
+        assert!(buffer.take_batch().is_empty());
+        assert_eq!(buffer.dropped, 0);
+    }
+
+    #[test]
+    fn picks_up_leftover_spill_file() {
+        let file = SpillFile::new("leftover");
+        let mut previous = Buffer::new(&config(2, 10, Some(&file.0)));
+        for topic in ["a", "b", "c"] {
+            previous.push(message(topic));
+        }
+        // Only what made it to disk survives a restart
+        drop(previous);
+        let mut buffer = Buffer::new(&config(2, 10, Some(&file.0)));
+        assert_eq!(buffer.on_disk, 2);
+        buffer.push(message("d"));
+        assert_eq!(topics(buffer.take_batch()), ["a", "b"]);
+        assert_eq!(topics(buffer.take_batch()), ["d"]);
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let file = SpillFile::new("corrupt");
+        let mut buffer = Buffer::new(&config(2, 10, Some(&file.0)));
+        for topic in ["a", "b", "c"] {
+            buffer.push(message(topic));
+        }
+        let mut spilled = OpenOptions::new().append(true).open(&file.0).unwrap();
+        spilled.write_all(b"not json\n").unwrap();
+        buffer.on_disk += 1;
+        assert_eq!(topics(buffer.take_batch()), ["a", "b"]);
+    }
+}
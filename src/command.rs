@@ -0,0 +1,284 @@
+//! Remote commands received on `<root>/<hostname>/command/<action>`
+//!
+//! The payload is either empty or a JSON object with optional `correlation_data`
+//! and `response_topic` fields next to the action specific arguments. The result
+//! is published, not retained, on the response topic, which defaults to
+//! `<root>/<hostname>/response/<action>`. With MQTT 5 the response topic and
+//! correlation data properties of the command take precedence over the payload.
+
+use std::sync::Arc;
+
+use chrono::SubsecRound;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    Error, Result,
+    config::{CommandsConfig, PowerBackendKind},
+    mqtt::{Message, Properties},
+    publisher::Publisher,
+};
+
+use self::exec::Exec;
+use self::power::{DryRunBackend, Power, PowerAction, PowerBackend, SystemBackend};
+
+pub mod exec;
+pub mod power;
+
+/// Topic level below the base topic commands are received on
+pub const COMMAND_TOPIC: &str = "command";
+/// Topic level below the base topic results are published on
+pub const RESPONSE_TOPIC: &str = "response";
+
+/// Action requested by a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Reply with `pong`, to check that commands are received
+    Ping,
+    /// Power management, optionally delayed by `delay` seconds
+    Power {
+        action: PowerAction,
+        delay: Option<u64>,
+    },
+    /// Cancel a delayed power action
+    Cancel,
+    /// Run the command configured under `name`
+    Exec { name: String },
+}
+
+impl Command {
+    /// Parse action from the topic and its arguments from the payload
+    pub fn parse(action: &str, args: &Map<String, Value>) -> Result<Self> {
+        if let Some(power_action) = PowerAction::from_action(action) {
+            let delay = match args.get("delay") {
+                Some(delay) => Some(delay.as_u64().ok_or_else(|| {
+                    Error::Command(format!("delay must be a number of seconds, got {delay}"))
+                })?),
+                None => None,
+            };
+            return Ok(Command::Power {
+                action: power_action,
+                delay,
+            });
+        }
+        match action {
+            "ping" => Ok(Command::Ping),
+            "cancel" => Ok(Command::Cancel),
+            "exec" => match args.get("name") {
+                Some(Value::String(name)) => Ok(Command::Exec { name: name.clone() }),
+                _ => Err(Error::Command("exec requires a \"name\" string".into())),
+            },
+            _ => Err(Error::Command(format!("unknown command {action:?}"))),
+        }
+    }
+}
+
+/// Fields common to all command payloads
+#[derive(Deserialize, Debug, Default)]
+struct Envelope {
+    correlation_data: Option<String>,
+    response_topic: Option<String>,
+    #[serde(flatten)]
+    args: Map<String, Value>,
+}
+
+/// Outcome of a command
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+/// Payload published on the response topic
+#[derive(Serialize, Debug)]
+pub struct Response {
+    pub action: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_data: Option<String>,
+}
+
+/// Parses incoming commands, executes them and publishes the result
+pub struct Dispatcher {
+    publisher: Publisher,
+    /// Shared by the dispatchers of all brokers, so pending actions can be cancelled from any
+    power: Arc<Power>,
+    exec: Arc<Exec>,
+}
+
+impl Dispatcher {
+    pub fn new(publisher: Publisher, config: &CommandsConfig) -> Self {
+        let backend: Arc<dyn PowerBackend> = match config.power.backend {
+            PowerBackendKind::System => Arc::new(SystemBackend),
+            PowerBackendKind::DryRun => Arc::new(DryRunBackend::default()),
+        };
+        Self {
+            publisher,
+            power: Arc::new(Power::new(config.power.clone(), backend)),
+            exec: Arc::new(Exec::new(config.exec.clone())),
+        }
+    }
+
+    /// Dispatcher for commands received through another broker
+    pub fn for_publisher(&self, publisher: Publisher) -> Self {
+        Self {
+            publisher,
+            power: self.power.clone(),
+            exec: self.exec.clone(),
+        }
+    }
+
+    /// Topic filter matching all commands
+    pub fn topic_filter(&self) -> String {
+        format!("{}/{COMMAND_TOPIC}/#", self.publisher.base_topic())
+    }
+
+    /// Action of a command topic, `None` if topic isn't a command
+    pub fn action<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic
+            .strip_prefix(self.publisher.base_topic())?
+            .strip_prefix('/')?
+            .strip_prefix(COMMAND_TOPIC)?
+            .strip_prefix('/')
+            .filter(|action| !action.is_empty())
+    }
+
+    /// Execute command and publish the result
+    pub async fn handle(&self, action: &str, message: &Message) {
+        let payload = message.payload.as_slice();
+        let envelope = match payload.iter().all(u8::is_ascii_whitespace) {
+            true => Ok(Envelope::default()),
+            false => serde_json::from_slice::<Envelope>(payload).map_err(Error::from),
+        };
+        let (response_topic, correlation_data, command) = match envelope {
+            Ok(envelope) => {
+                let command = match Command::parse(action, &envelope.args) {
+                    // A retained command would run again on every connect
+                    Ok(_) if message.retain => {
+                        Err(Error::Command("retained commands are ignored".into()))
+                    }
+                    command => command,
+                };
+                (envelope.response_topic, envelope.correlation_data, command)
+            }
+            Err(e) => (None, None, Err(e)),
+        };
+        let correlation_data = match &message.correlation_data {
+            Some(data) => Some(String::from_utf8_lossy(data).into_owned()),
+            None => correlation_data,
+        };
+        let reply = Reply {
+            publisher: self.publisher.clone(),
+            action: action.to_string(),
+            topic: message
+                .response_topic
+                .clone()
+                .or(response_topic)
+                .unwrap_or_else(|| {
+                    format!("{}/{RESPONSE_TOPIC}/{action}", self.publisher.base_topic())
+                }),
+            correlation_property: message
+                .correlation_data
+                .clone()
+                .or_else(|| correlation_data.clone().map(String::into_bytes)),
+            correlation_data,
+        };
+        let result = match command {
+            Ok(command) => self.execute(command, &reply).await,
+            Err(e) => Err(e),
+        };
+        reply.send(result).await;
+    }
+
+    /// Execute command, a delayed power action only gets scheduled
+    ///
+    /// The outcome of a delayed power action is sent as a second response once its
+    /// delay has passed, or it has been cancelled.
+    async fn execute(&self, command: Command, reply: &Reply) -> Result<Value> {
+        match command {
+            Command::Ping => Ok("pong".into()),
+            Command::Power { action, delay } => {
+                let scheduled = self.power.schedule(action, delay)?;
+                if scheduled.delay.is_zero() {
+                    self.power.run(scheduled).await?;
+                    return Ok(action.as_str().into());
+                }
+                let acknowledgement = serde_json::json!({
+                    "action": action.as_str(),
+                    "at": scheduled.at.trunc_subsecs(0).to_rfc3339(),
+                    "delay": scheduled.delay.as_secs(),
+                });
+                let power = self.power.clone();
+                let reply = reply.clone();
+                tokio::spawn(async move {
+                    let outcome = power.run(scheduled).await;
+                    reply.send(outcome.map(|()| action.as_str().into())).await;
+                });
+                Ok(acknowledgement)
+            }
+            Command::Cancel => match self.power.cancel() {
+                Some(action) => Ok(action.as_str().into()),
+                None => Err(Error::Command("no power action to cancel".into())),
+            },
+            Command::Exec { name } => Ok(serde_json::to_value(self.exec.run(&name).await?)?),
+        }
+    }
+}
+
+/// Where the response to a command is published
+#[derive(Clone)]
+struct Reply {
+    publisher: Publisher,
+    action: String,
+    topic: String,
+    /// Included in the payload
+    correlation_data: Option<String>,
+    /// MQTT 5 correlation data property
+    correlation_property: Option<Vec<u8>>,
+}
+
+impl Reply {
+    /// Publish the result of the command
+    async fn send(&self, result: Result<Value>) {
+        let action = &self.action;
+        let response = match result {
+            Ok(result) => Response {
+                action: action.clone(),
+                status: Status::Ok,
+                result: Some(result),
+                error: None,
+                correlation_data: self.correlation_data.clone(),
+            },
+            Err(e) => {
+                eprintln!("command_{action}_error={e}");
+                Response {
+                    action: action.clone(),
+                    status: Status::Error,
+                    result: None,
+                    error: Some(e.to_string()),
+                    correlation_data: self.correlation_data.clone(),
+                }
+            }
+        };
+        match serde_json::to_vec(&response) {
+            Ok(payload) => {
+                self.publisher
+                    .respond(
+                        &self.topic,
+                        payload,
+                        Properties {
+                            correlation_data: self.correlation_property.clone(),
+                            ..Properties::json()
+                        },
+                    )
+                    .await
+            }
+            Err(e) => eprintln!("command_{action}_response_error={e}"),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Command as Process,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+use crate::{
+    Error, Result,
+    config::{ExecCommandConfig, ExecConfig},
+};
+
+/// Result of running an allowlisted command
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExecOutput {
+    /// `None` if killed by a signal or after timing out
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether output past `max_output` bytes was dropped
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+}
+
+/// Variables passed on from modo's own environment
+///
+/// Others, like `MODO_MQTT_URL` with the broker password, are only set when
+/// configured in `env`.
+#[cfg(not(windows))]
+const INHERITED_ENV: [&str; 8] = [
+    "PATH", "HOME", "USER", "LOGNAME", "LANG", "LC_ALL", "TZ", "TMPDIR",
+];
+#[cfg(windows)]
+const INHERITED_ENV: [&str; 12] = [
+    "PATH",
+    "PATHEXT",
+    "SystemRoot",
+    "SystemDrive",
+    "WINDIR",
+    "COMSPEC",
+    "TEMP",
+    "TMP",
+    "USERNAME",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+];
+
+/// How long to keep reading output after the command exited
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Runs commands defined in configuration, never command lines from a payload
+pub struct Exec {
+    config: ExecConfig,
+}
+
+impl Exec {
+    pub fn new(config: ExecConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run the named command, without a shell, and capture its output
+    pub async fn run(&self, name: &str) -> Result<ExecOutput> {
+        let command =
+            self.config.commands.get(name).ok_or_else(|| {
+                Error::Command(format!("exec command {name:?} is not configured"))
+            })?;
+        run(command, self.config.max_output).await
+    }
+}
+
+async fn run(command: &ExecCommandConfig, max_output: usize) -> Result<ExecOutput> {
+    let mut process = Process::new(&command.program);
+    process
+        .args(&command.args)
+        .env_clear()
+        .envs(
+            INHERITED_ENV
+                .iter()
+                .filter_map(|name| Some((name, std::env::var_os(name)?))),
+        )
+        .envs(&command.env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(working_dir) = &command.working_dir {
+        process.current_dir(working_dir);
+    }
+    let mut child = process.spawn()?;
+    // Drain both pipes concurrently, so the child never blocks on a full pipe
+    let stdout = child.stdout.take().map(|pipe| capture(pipe, max_output));
+    let stderr = child.stderr.take().map(|pipe| capture(pipe, max_output));
+
+    let timeout = Duration::from_secs(command.timeout);
+    let (status, timed_out) = match time::timeout(timeout, child.wait()).await {
+        Ok(status) => (status?, false),
+        Err(_) => {
+            // The child may have exited in the meantime
+            let _ = child.start_kill();
+            (child.wait().await?, true)
+        }
+    };
+    // Grandchildren may keep the pipes open, don't wait for them for long
+    let grace = Instant::now() + OUTPUT_GRACE;
+    let (stdout, stdout_truncated) = collect(stdout, grace).await;
+    let (stderr, stderr_truncated) = collect(stderr, grace).await;
+    Ok(ExecOutput {
+        exit_code: status.code().filter(|_| !timed_out),
+        timed_out,
+        stdout,
+        stderr,
+        stdout_truncated,
+        stderr_truncated,
+    })
+}
+
+/// Output captured so far, whether some was dropped, and the task reading it
+type Capture = (Arc<Mutex<(Vec<u8>, bool)>>, JoinHandle<()>);
+
+/// Read a pipe to the end in the background, keeping at most `max_output` bytes
+fn capture<R: AsyncRead + Unpin + Send + 'static>(mut pipe: R, max_output: usize) -> Capture {
+    let output = Arc::new(Mutex::new((Vec::new(), false)));
+    let captured = output.clone();
+    let handle = tokio::spawn(async move {
+        let mut buffer = [0; 4096];
+        while let Ok(read) = pipe.read(&mut buffer).await {
+            if read == 0 {
+                break;
+            }
+            let Ok(mut output) = captured.lock() else {
+                break;
+            };
+            let (output, truncated) = &mut *output;
+            let keep = read.min(max_output.saturating_sub(output.len()));
+            output.extend_from_slice(&buffer[..keep]);
+            *truncated |= keep < read;
+        }
+    });
+    (output, handle)
+}
+
+/// Output read until the pipe closed or the deadline passed, and whether it was truncated
+async fn collect(capture: Option<Capture>, deadline: Instant) -> (String, bool) {
+    let Some((output, mut handle)) = capture else {
+        return (String::new(), false);
+    };
+    if time::timeout_at(deadline, &mut handle).await.is_err() {
+        handle.abort();
+    }
+    let (output, truncated) = output.lock().map(|o| o.clone()).unwrap_or_default();
+    (String::from_utf8_lossy(&output).into_owned(), truncated)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn command(program: &str, args: &[&str], timeout: u64) -> ExecCommandConfig {
+        ExecCommandConfig {
+            program: program.into(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            working_dir: None,
+            timeout,
+            env: BTreeMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn exit_code_is_reported() {
+        let output = run(&command("true", &[], 10), 100).await.unwrap();
+        assert_eq!(output.exit_code, Some(0));
+        assert!(!output.timed_out);
+        let output = run(&command("false", &[], 10), 100).await.unwrap();
+        assert_eq!(output.exit_code, Some(1));
+    }
+
+    #[tokio::test]
+    async fn output_is_captured() {
+        let output = run(&command("echo", &["hello", "world"], 10), 100)
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, "hello world\n");
+        assert_eq!(output.stderr, "");
+        assert!(!output.stdout_truncated);
+    }
+
+    #[tokio::test]
+    async fn output_is_truncated() {
+        let output = run(&command("seq", &["1", "10000"], 10), 100)
+            .await
+            .unwrap();
+        assert_eq!(output.exit_code, Some(0));
+        assert_eq!(output.stdout.len(), 100);
+        assert!(output.stdout.starts_with("1\n2\n3\n"));
+        assert!(output.stdout_truncated);
+        assert!(!output.stderr_truncated);
+    }
+
+    #[tokio::test]
+    async fn slow_command_times_out() {
+        let started = Instant::now();
+        let output = run(&command("sleep", &["10"], 1), 100).await.unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.exit_code, None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn missing_program_is_an_error() {
+        let result = run(&command("/nonexistent/modo-test", &[], 10), 100).await;
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn environment_is_limited_to_allowlist_and_configured_variables() {
+        let mut command = command("env", &[], 10);
+        command.env.insert("MODO_TEST".into(), "configured".into());
+        let output = run(&command, 1 << 16).await.unwrap();
+        assert!(
+            output
+                .stdout
+                .lines()
+                .any(|line| line == "MODO_TEST=configured")
+        );
+        for line in output.stdout.lines() {
+            let name = line.split_once('=').map_or(line, |(name, _)| name);
+            assert!(
+                name == "MODO_TEST" || INHERITED_ENV.contains(&name),
+                "{line}"
+            );
+        }
+    }
+}
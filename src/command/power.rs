@@ -0,0 +1,341 @@
+use std::{
+    fmt,
+    process::Command as Process,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tokio::{
+    sync::watch,
+    task,
+    time::{self, Instant},
+};
+
+use crate::{Error, Result, config::PowerConfig};
+
+/// Power management action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    Shutdown,
+    Reboot,
+    Suspend,
+    Hibernate,
+    Lock,
+}
+
+impl PowerAction {
+    pub fn from_action(action: &str) -> Option<Self> {
+        match action {
+            "shutdown" => Some(PowerAction::Shutdown),
+            "reboot" => Some(PowerAction::Reboot),
+            "suspend" => Some(PowerAction::Suspend),
+            "hibernate" => Some(PowerAction::Hibernate),
+            "lock" => Some(PowerAction::Lock),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PowerAction::Shutdown => "shutdown",
+            PowerAction::Reboot => "reboot",
+            PowerAction::Suspend => "suspend",
+            PowerAction::Hibernate => "hibernate",
+            PowerAction::Lock => "lock",
+        }
+    }
+}
+
+impl fmt::Display for PowerAction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+/// Performs power actions
+pub trait PowerBackend: Send + Sync {
+    fn execute(&self, action: PowerAction) -> Result<()>;
+}
+
+/// Runs the operating system's power management commands
+pub struct SystemBackend;
+
+impl PowerBackend for SystemBackend {
+    fn execute(&self, action: PowerAction) -> Result<()> {
+        let (program, args) = system_command(action);
+        let status = Process::new(program).args(args).status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(Error::Command(format!(
+                "{program} {args:?} failed with {status}"
+            ))),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn system_command(action: PowerAction) -> (&'static str, &'static [&'static str]) {
+    match action {
+        PowerAction::Shutdown => ("shutdown", &["/s", "/t", "0"]),
+        PowerAction::Reboot => ("shutdown", &["/r", "/t", "0"]),
+        PowerAction::Suspend => ("rundll32.exe", &["powrprof.dll,SetSuspendState", "0,1,0"]),
+        PowerAction::Hibernate => ("shutdown", &["/h"]),
+        PowerAction::Lock => ("rundll32.exe", &["user32.dll,LockWorkStation"]),
+    }
+}
+
+#[cfg(not(windows))]
+fn system_command(action: PowerAction) -> (&'static str, &'static [&'static str]) {
+    match action {
+        PowerAction::Shutdown => ("systemctl", &["poweroff"]),
+        PowerAction::Reboot => ("systemctl", &["reboot"]),
+        PowerAction::Suspend => ("systemctl", &["suspend"]),
+        PowerAction::Hibernate => ("systemctl", &["hibernate"]),
+        PowerAction::Lock => ("loginctl", &["lock-sessions"]),
+    }
+}
+
+/// Only records and logs actions, for testing and CI
+#[derive(Default)]
+pub struct DryRunBackend {
+    executed: Mutex<Vec<PowerAction>>,
+}
+
+impl DryRunBackend {
+    /// Actions executed so far
+    pub fn executed(&self) -> Vec<PowerAction> {
+        self.executed.lock().map(|e| e.clone()).unwrap_or_default()
+    }
+}
+
+impl PowerBackend for DryRunBackend {
+    fn execute(&self, action: PowerAction) -> Result<()> {
+        println!("power_dry_run={action}");
+        if let Ok(mut executed) = self.executed.lock() {
+            executed.push(action);
+        }
+        Ok(())
+    }
+}
+
+/// Power action waiting for its delay to pass
+#[derive(Debug)]
+pub struct Scheduled {
+    pub action: PowerAction,
+    /// Zero for actions executed right away
+    pub delay: Duration,
+    /// When the action is executed
+    pub at: DateTime<Utc>,
+    deadline: Instant,
+    /// Id in `Power::pending`, to tell whether it has been cancelled
+    id: u64,
+}
+
+/// Executes enabled power actions, optionally after a delay during which they can be cancelled
+pub struct Power {
+    config: PowerConfig,
+    backend: Arc<dyn PowerBackend>,
+    /// Id and action of the currently scheduled action, the id is bumped to cancel it
+    pending: watch::Sender<(u64, Option<PowerAction>)>,
+}
+
+impl Power {
+    pub fn new(config: PowerConfig, backend: Arc<dyn PowerBackend>) -> Self {
+        Self {
+            config,
+            backend,
+            pending: watch::Sender::new((0, None)),
+        }
+    }
+
+    fn enabled(&self, action: PowerAction) -> bool {
+        match action {
+            PowerAction::Shutdown => self.config.shutdown,
+            PowerAction::Reboot => self.config.reboot,
+            PowerAction::Suspend => self.config.suspend,
+            PowerAction::Hibernate => self.config.hibernate,
+            PowerAction::Lock => self.config.lock,
+        }
+    }
+
+    /// Execute action after the delay (or the configured default), unless cancelled meanwhile
+    pub async fn execute(&self, action: PowerAction, delay: Option<u64>) -> Result<()> {
+        let scheduled = self.schedule(action, delay)?;
+        self.run(scheduled).await
+    }
+
+    /// Make action pending for the delay (or the configured default), replacing any pending one
+    pub fn schedule(&self, action: PowerAction, delay: Option<u64>) -> Result<Scheduled> {
+        if !self.enabled(action) {
+            return Err(Error::Command(format!("power action {action} is disabled")));
+        }
+        let delay = Duration::from_secs(delay.unwrap_or(self.config.delay));
+        let too_long = || {
+            Error::Command(format!(
+                "power action delay {}s is too long",
+                delay.as_secs()
+            ))
+        };
+        let deadline = Instant::now().checked_add(delay).ok_or_else(too_long)?;
+        let at = TimeDelta::from_std(delay)
+            .ok()
+            .and_then(|delay| Utc::now().checked_add_signed(delay))
+            .ok_or_else(too_long)?;
+        let mut id = 0;
+        if !delay.is_zero() {
+            self.pending.send_modify(|pending| {
+                pending.0 += 1;
+                pending.1 = Some(action);
+                id = pending.0;
+            });
+        }
+        Ok(Scheduled {
+            action,
+            delay,
+            at,
+            deadline,
+            id,
+        })
+    }
+
+    /// Wait out the delay of a scheduled action, then execute it unless cancelled meanwhile
+    pub async fn run(&self, scheduled: Scheduled) -> Result<()> {
+        let Scheduled {
+            action,
+            delay,
+            deadline,
+            id,
+            ..
+        } = scheduled;
+        if !delay.is_zero() {
+            // A newer action replaces this one, so it's cancelled as well
+            let mut pending = self.pending.subscribe();
+            tokio::select! {
+                _ = time::sleep_until(deadline) => {}
+                _ = pending.wait_for(|pending| pending.0 != id) => {}
+            }
+            let current = self.pending.send_if_modified(|pending| {
+                let current = pending.0 == id;
+                if current {
+                    pending.1 = None;
+                }
+                current
+            });
+            if !current {
+                return Err(Error::Command(format!("power action {action} cancelled")));
+            }
+        }
+        // Power management commands return quickly, but block meanwhile
+        let backend = self.backend.clone();
+        task::spawn_blocking(move || backend.execute(action))
+            .await
+            .map_err(|e| Error::Command(e.to_string()))?
+    }
+
+    /// Cancel the scheduled action, if any
+    pub fn cancel(&self) -> Option<PowerAction> {
+        let mut cancelled = None;
+        self.pending.send_if_modified(|pending| {
+            cancelled = pending.1.take();
+            if cancelled.is_some() {
+                pending.0 += 1;
+            }
+            cancelled.is_some()
+        });
+        cancelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn power(delay: u64) -> (Arc<Power>, Arc<DryRunBackend>) {
+        let backend = Arc::new(DryRunBackend::default());
+        let config = PowerConfig {
+            delay,
+            reboot: true,
+            lock: true,
+            ..Default::default()
+        };
+        (Arc::new(Power::new(config, backend.clone())), backend)
+    }
+
+    /// Schedule the action in the background, once it's pending
+    async fn schedule(
+        power: &Arc<Power>,
+        action: PowerAction,
+        delay: Option<u64>,
+    ) -> task::JoinHandle<Result<()>> {
+        let mut pending = power.pending.subscribe();
+        let id = pending.borrow().0;
+        let handle = tokio::spawn({
+            let power = power.clone();
+            async move { power.execute(action, delay).await }
+        });
+        pending.wait_for(|pending| pending.0 > id).await.unwrap();
+        handle
+    }
+
+    #[tokio::test]
+    async fn immediate_action_reaches_backend() {
+        let (power, backend) = power(0);
+        power.execute(PowerAction::Lock, None).await.unwrap();
+        assert_eq!(backend.executed(), [PowerAction::Lock]);
+    }
+
+    #[tokio::test]
+    async fn disabled_action_is_rejected() {
+        let (power, backend) = power(0);
+        assert!(matches!(
+            power.execute(PowerAction::Shutdown, None).await,
+            Err(Error::Command(_))
+        ));
+        assert!(backend.executed().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_action_never_executes() {
+        let (power, backend) = power(3600);
+        let delayed = schedule(&power, PowerAction::Reboot, None).await;
+        assert_eq!(power.cancel(), Some(PowerAction::Reboot));
+        assert!(delayed.await.unwrap().is_err());
+        assert!(backend.executed().is_empty());
+        assert_eq!(power.cancel(), None);
+    }
+
+    #[tokio::test]
+    async fn newer_action_replaces_pending_one() {
+        let (power, backend) = power(3600);
+        let delayed = schedule(&power, PowerAction::Reboot, None).await;
+        let replacing = schedule(&power, PowerAction::Lock, Some(1)).await;
+        assert!(delayed.await.unwrap().is_err());
+        replacing.await.unwrap().unwrap();
+        assert_eq!(backend.executed(), [PowerAction::Lock]);
+    }
+
+    #[tokio::test]
+    async fn scheduled_action_tells_when_it_runs() {
+        let (power, backend) = power(60);
+        let earliest = Utc::now() + TimeDelta::seconds(60);
+        let scheduled = power.schedule(PowerAction::Reboot, None).unwrap();
+        assert_eq!(scheduled.delay, Duration::from_secs(60));
+        assert!(scheduled.at >= earliest);
+        assert!(scheduled.at <= Utc::now() + TimeDelta::seconds(60));
+        // Cancelled before it started waiting
+        assert_eq!(power.cancel(), Some(PowerAction::Reboot));
+        assert!(power.run(scheduled).await.is_err());
+        assert!(backend.executed().is_empty());
+    }
+
+    #[tokio::test]
+    async fn huge_delay_is_rejected() {
+        let (power, backend) = power(0);
+        assert!(matches!(
+            power.execute(PowerAction::Reboot, Some(u64::MAX)).await,
+            Err(Error::Command(_))
+        ));
+        assert!(backend.executed().is_empty());
+    }
+}
@@ -0,0 +1,1063 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{Args, Error, Result};
+
+/// Runtime configuration
+///
+/// Values are resolved with the following precedence (highest first):
+/// 1. Command line flags
+/// 2. Environment variables (`MODO_*`)
+/// 3. Configuration file (`--config` or the per-user default location)
+/// 4. Built-in defaults
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mqtt_url: MqttUrl,
+    pub threshold_active: u64,
+    pub threshold_idle: u64,
+    pub mqtt_root_topic: String,
+    pub output_mode: OutputMode,
+    pub mqtt: MqttConfig,
+    pub publish: PublishConfig,
+    pub state: StateConfig,
+    pub shutdown: ShutdownConfig,
+    pub buffer: BufferConfig,
+    pub reconnect: ReconnectConfig,
+    pub failover: FailoverConfig,
+    /// Other brokers all values are published to as well
+    pub mirrors: Vec<MirrorConfig>,
+    pub homeassistant: HomeAssistantConfig,
+    pub sensors: SensorsConfig,
+    pub commands: CommandsConfig,
+    pub notify: NotifyConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mqtt_url: MqttUrl::default(),
+            threshold_active: 30,
+            threshold_idle: 5 * 60,
+            mqtt_root_topic: "modo".into(),
+            output_mode: OutputMode::default(),
+            mqtt: MqttConfig::default(),
+            publish: PublishConfig::default(),
+            state: StateConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            buffer: BufferConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            failover: FailoverConfig::default(),
+            mirrors: Vec::new(),
+            homeassistant: HomeAssistantConfig::default(),
+            sensors: SensorsConfig::default(),
+            commands: CommandsConfig::default(),
+            notify: NotifyConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration file (if any) and merge it with command line and environment
+    pub(crate) fn load(args: Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => match default_path() {
+                Some(path) if path.is_file() => Self::from_file(&path)?,
+                _ => Self::default(),
+            },
+        };
+        if let Some(mqtt_url) = args.mqtt_url {
+            config.mqtt_url = MqttUrl(mqtt_url);
+        }
+        if let Some(threshold_active) = args.threshold_active {
+            config.threshold_active = threshold_active;
+        }
+        if let Some(threshold_idle) = args.threshold_idle {
+            config.threshold_idle = threshold_idle;
+        }
+        if let Some(mqtt_root_topic) = args.mqtt_root_topic {
+            config.mqtt_root_topic = mqtt_root_topic;
+        }
+        config.validate()?;
+        // MQTT 3.1.1 has no user properties to carry the original time in
+        if config.buffer.enabled == Some(true) && config.mqtt.version != MqttVersion::V5 {
+            eprintln!(
+                "config_warning=buffered readings are replayed without their original time, as that needs mqtt.version = \"5\""
+            );
+        }
+        Ok(config)
+    }
+
+    /// Parse configuration from a TOML file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("unable to read {}: {e}", path.display())))?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// The primary broker followed by the backup brokers
+    pub fn broker_urls(&self) -> Vec<&MqttUrl> {
+        std::iter::once(&self.mqtt_url)
+            .chain(&self.failover.backup_urls)
+            .collect()
+    }
+
+    /// Ensure the merged configuration is usable
+    pub fn validate(&self) -> Result<()> {
+        if self.mqtt_url.0.is_empty() {
+            return Err(Error::Config(
+                "mqtt_url must be set on the command line, in MODO_MQTT_URL or in the config file"
+                    .into(),
+            ));
+        }
+        if self.threshold_active >= self.threshold_idle {
+            return Err(Error::Config(format!(
+                "threshold_active ({}) must be less than threshold_idle ({})",
+                self.threshold_active, self.threshold_idle
+            )));
+        }
+        validate_root_topic("mqtt_root_topic", &self.mqtt_root_topic)?;
+        for (sensor, interval) in self.sensors.intervals() {
+            if interval == 0 {
+                return Err(Error::Config(format!(
+                    "sensors.{sensor}.interval must be at least 1"
+                )));
+            }
+        }
+        for (name, policy) in self.publish.policies() {
+            if policy.deadband.is_some_and(|d| d < 0.0)
+                || policy.deadband_percent.is_some_and(|d| d < 0.0)
+            {
+                return Err(Error::Config(format!(
+                    "publish.{name} deadband must not be negative"
+                )));
+            }
+            if policy.qos.is_some_and(|qos| qos > 2) {
+                return Err(Error::Config(format!(
+                    "publish.{name}.qos must be 0, 1 or 2"
+                )));
+            }
+            if let (Some(min), Some(max)) = (policy.min_interval, policy.max_interval)
+                && min > max
+            {
+                return Err(Error::Config(format!(
+                    "publish.{name}.min_interval ({min}) must not exceed max_interval ({max})"
+                )));
+            }
+        }
+        if self.mqtt.request_capacity == 0 {
+            return Err(Error::Config(
+                "mqtt.request_capacity must be at least 1".into(),
+            ));
+        }
+        if self.buffer.is_enabled() && self.buffer.max_memory == 0 {
+            return Err(Error::Config("buffer.max_memory must be at least 1".into()));
+        }
+        let reconnect = &self.reconnect;
+        if reconnect.initial_delay == 0 || reconnect.initial_delay > reconnect.max_delay {
+            return Err(Error::Config(format!(
+                "reconnect.initial_delay ({}) must be between 1 and max_delay ({})",
+                reconnect.initial_delay, reconnect.max_delay
+            )));
+        }
+        if reconnect.multiplier < 1.0 {
+            return Err(Error::Config(
+                "reconnect.multiplier must be at least 1".into(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&reconnect.jitter) {
+            return Err(Error::Config(
+                "reconnect.jitter must be between 0 and 1".into(),
+            ));
+        }
+        if self.failover.backup_urls.iter().any(|url| url.0.is_empty()) {
+            return Err(Error::Config(
+                "failover.backup_urls must not be empty".into(),
+            ));
+        }
+        if self.failover.attempts == 0 || self.failover.primary_check_interval == 0 {
+            return Err(Error::Config(
+                "failover.attempts and failover.primary_check_interval must be at least 1".into(),
+            ));
+        }
+        self.mqtt.tls.validate("mqtt.tls")?;
+        for (index, mirror) in self.mirrors.iter().enumerate() {
+            if mirror.url.0.is_empty() {
+                return Err(Error::Config(format!("mirrors[{index}].url must be set")));
+            }
+            if let Some(root_topic) = &mirror.root_topic {
+                validate_root_topic(&format!("mirrors[{index}].root_topic"), root_topic)?;
+            }
+            if mirror.qos.is_some_and(|qos| qos > 2) {
+                return Err(Error::Config(format!(
+                    "mirrors[{index}].qos must be 0, 1 or 2"
+                )));
+            }
+            mirror.tls.validate(&format!("mirrors[{index}].tls"))?;
+        }
+        for (name, expiry) in &self.mqtt.message_expiry {
+            if *expiry == 0 {
+                return Err(Error::Config(format!(
+                    "mqtt.message_expiry.{name} must be at least 1"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Root topics are prepended to `/<hostname>`, so they can't end with a slash
+fn validate_root_topic(name: &str, root_topic: &str) -> Result<()> {
+    if root_topic.is_empty() || root_topic.contains(['+', '#']) || root_topic.ends_with('/') {
+        return Err(Error::Config(format!(
+            "{name} {root_topic:?} must be non-empty, without wildcards or trailing slash"
+        )));
+    }
+    Ok(())
+}
+
+/// Topic layout below the `<root>/<hostname>` base topic
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// One retained topic per metric, e.g. `idle_seconds`
+    #[default]
+    Plain,
+    /// Homie 4 convention device, e.g. `idle/idle-seconds`
+    Homie,
+}
+
+impl OutputMode {
+    /// Topic (relative to the base topic) a metric of a sensor is published on
+    pub fn metric_path(&self, sensor: &str, metric: &str) -> String {
+        match self {
+            OutputMode::Plain => metric.to_string(),
+            OutputMode::Homie => crate::homie::property_path(sensor, metric),
+        }
+    }
+
+    /// Topic (relative to the base topic) and payloads signalling availability
+    pub fn availability(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            OutputMode::Plain => ("connected", "true", "false"),
+            OutputMode::Homie => ("$state", "ready", "lost"),
+        }
+    }
+}
+
+/// MQTT protocol settings
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub version: MqttVersion,
+    /// Extra MQTT 5 user properties attached to every message, next to `hostname` and `version`
+    pub user_properties: BTreeMap<String, String>,
+    /// Seconds before the broker discards a message, by metric or sensor name (MQTT 5 only)
+    pub message_expiry: BTreeMap<String, u32>,
+    /// Messages queued for the connection before publishing blocks
+    pub request_capacity: usize,
+    pub tls: TlsConfig,
+    pub websocket: WebSocketConfig,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            version: MqttVersion::default(),
+            user_properties: BTreeMap::new(),
+            message_expiry: BTreeMap::from([("idle_seconds".into(), 60)]),
+            request_capacity: 100,
+            tls: TlsConfig::default(),
+            websocket: WebSocketConfig::default(),
+        }
+    }
+}
+
+/// Settings for `ws://` and `wss://` connections
+///
+/// The path is taken from the MQTT URL, e.g. `wss://example.com/mqtt`.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// Extra HTTP headers sent with the WebSocket upgrade request
+    pub headers: BTreeMap<String, String>,
+}
+
+impl core::fmt::Debug for WebSocketConfig {
+    /// Header values often carry credentials, only show the names
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.debug_struct("WebSocketConfig")
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// TLS settings for `mqtts://` and `wss://` connections
+///
+/// Without `ca_file` or `fingerprint` the platform root certificates are used.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the certificates the server certificate has to chain up to
+    pub ca_file: Option<PathBuf>,
+    /// SHA-256 fingerprint of the only server certificate to accept, in hex with optional colons
+    pub fingerprint: Option<String>,
+    /// PEM file with the client certificate chain, for mutual TLS
+    pub client_cert: Option<PathBuf>,
+    /// PEM file with the private key of the client certificate
+    pub client_key: Option<PathBuf>,
+    /// Protocols offered through ALPN, e.g. `["mqtt"]`
+    pub alpn: Vec<String>,
+}
+
+impl TlsConfig {
+    /// Whether anything differs from the platform defaults
+    pub fn is_custom(&self) -> bool {
+        self.ca_file.is_some()
+            || self.fingerprint.is_some()
+            || self.client_cert.is_some()
+            || self.client_key.is_some()
+            || !self.alpn.is_empty()
+    }
+
+    fn validate(&self, name: &str) -> Result<()> {
+        if self.client_cert.is_some() != self.client_key.is_some() {
+            return Err(Error::Config(format!(
+                "{name}.client_cert and {name}.client_key must be given together"
+            )));
+        }
+        if self.ca_file.is_some() && self.fingerprint.is_some() {
+            return Err(Error::Config(format!(
+                "{name}.ca_file and {name}.fingerprint can't be combined"
+            )));
+        }
+        self.fingerprint(name)?;
+        Ok(())
+    }
+
+    /// SHA-256 fingerprint as bytes, if configured
+    pub fn fingerprint(&self, name: &str) -> Result<Option<[u8; 32]>> {
+        let Some(fingerprint) = &self.fingerprint else {
+            return Ok(None);
+        };
+        let invalid = || {
+            Error::Config(format!(
+                "{name}.fingerprint must be 64 hex digits, optionally separated by colons"
+            ))
+        };
+        let digits = fingerprint.replace(':', "");
+        if digits.len() != 64 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte =
+                u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Some(bytes))
+    }
+}
+
+/// MQTT protocol version
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MqttVersion {
+    #[default]
+    #[serde(rename = "3.1.1")]
+    V311,
+    #[serde(rename = "5")]
+    V5,
+}
+
+/// When and how readings are published
+///
+/// A metric uses the policy configured for its id, then for its sensor, then the default.
+/// Unset fields fall back in the same order. Other topics, like `connected` or `state`,
+/// use the policy configured for the topic for QoS and retain.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishConfig {
+    pub default: PolicyConfig,
+    /// Policies by metric id (e.g. `idle_seconds`) or sensor name (e.g. `cpu`)
+    pub metrics: BTreeMap<String, PolicyConfig>,
+}
+
+impl PublishConfig {
+    /// Default and per metric policies with their config path
+    fn policies(&self) -> impl Iterator<Item = (String, &PolicyConfig)> {
+        std::iter::once(("default".to_string(), &self.default)).chain(
+            self.metrics
+                .iter()
+                .map(|(name, policy)| (format!("metrics.{name:?}"), policy)),
+        )
+    }
+
+    /// Policy of a metric, with unset fields filled in from the sensor and default policies
+    pub fn policy(&self, sensor: &str, metric: &str) -> PolicyConfig {
+        [
+            self.metrics.get(metric),
+            self.metrics.get(sensor),
+            Some(&self.default),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(PolicyConfig::default(), |policy, fallback| PolicyConfig {
+            on_change: policy.on_change.or(fallback.on_change),
+            deadband: policy.deadband.or(fallback.deadband),
+            deadband_percent: policy.deadband_percent.or(fallback.deadband_percent),
+            min_interval: policy.min_interval.or(fallback.min_interval),
+            max_interval: policy.max_interval.or(fallback.max_interval),
+            qos: policy.qos.or(fallback.qos),
+            retain: policy.retain.or(fallback.retain),
+        })
+    }
+}
+
+/// Publish policy of a metric, every reading is published unless configured otherwise
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Only publish values which differ from the last published value
+    pub on_change: Option<bool>,
+    /// Numeric values count as changed when they differ by more than this
+    pub deadband: Option<f64>,
+    /// Numeric values count as changed when they differ by more than this percentage
+    ///
+    /// With both deadbands set a change has to exceed both.
+    pub deadband_percent: Option<f64>,
+    /// Seconds to wait after publishing before publishing again
+    pub min_interval: Option<u64>,
+    /// Seconds after which an unchanged value is published again
+    pub max_interval: Option<u64>,
+    /// MQTT QoS level 0, 1 or 2, defaults to 1
+    pub qos: Option<u8>,
+    /// Whether the broker keeps the last value for new subscribers, defaults to true
+    pub retain: Option<bool>,
+}
+
+/// Consolidated state snapshot settings
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateConfig {
+    /// Publish all values as one JSON object on `<root>/<hostname>/state`
+    pub enabled: bool,
+}
+
+/// What happens when modo is stopped with SIGINT or SIGTERM
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Remove the retained values of all metrics and the state snapshot
+    pub clear_retained: bool,
+}
+
+/// Buffering of readings while the broker is unreachable
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BufferConfig {
+    /// Keep readings while disconnected and publish them once reconnected, on unless disabled
+    pub enabled: Option<bool>,
+    /// Messages kept in memory
+    pub max_memory: usize,
+    /// Messages kept in the spill file
+    pub max_disk: usize,
+    /// Spill file, messages are only kept in memory if unset
+    pub path: Option<PathBuf>,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            max_memory: 1000,
+            max_disk: 100_000,
+            path: None,
+        }
+    }
+}
+
+impl BufferConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// Backoff between connection attempts
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconnectConfig {
+    /// Seconds to wait after the first failure
+    pub initial_delay: u64,
+    /// Upper bound of the delay in seconds
+    pub max_delay: u64,
+    /// Factor the delay grows by after every failure
+    pub multiplier: f64,
+    /// Fraction the delay is randomly varied by, so hosts don't reconnect all at once
+    pub jitter: f64,
+    /// Failed attempts in a row before giving up and exiting, unlimited if unset
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: 1,
+            max_delay: 300,
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_retries: None,
+        }
+    }
+}
+
+/// Backup brokers used while the primary broker (`mqtt_url`) is unreachable
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailoverConfig {
+    /// Tried in order after the primary broker
+    pub backup_urls: Vec<MqttUrl>,
+    /// Failed attempts in a row before moving on to the next broker
+    pub attempts: u32,
+    /// Seconds between checks whether the primary broker is back, while on a backup
+    pub primary_check_interval: u64,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            backup_urls: Vec::new(),
+            attempts: 3,
+            primary_check_interval: 60,
+        }
+    }
+}
+
+/// Broker which gets a copy of everything published to the primary broker
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// Broker URL, with the credentials for this broker
+    pub url: MqttUrl,
+    /// Root topic on this broker, defaults to `mqtt_root_topic`
+    pub root_topic: Option<String>,
+    /// QoS of all messages to this broker, instead of the publish policies
+    pub qos: Option<u8>,
+    /// Accept commands from this broker, if commands are enabled
+    pub commands: bool,
+    pub tls: TlsConfig,
+    pub websocket: WebSocketConfig,
+}
+
+/// Home Assistant MQTT discovery settings
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HomeAssistantConfig {
+    /// Publish discovery config so entities show up in Home Assistant automatically
+    pub discovery: bool,
+    /// Topic prefix Home Assistant listens on for discovery config
+    pub discovery_prefix: String,
+}
+
+impl Default for HomeAssistantConfig {
+    fn default() -> Self {
+        Self {
+            discovery: false,
+            discovery_prefix: "homeassistant".into(),
+        }
+    }
+}
+
+/// Settings for each sensor
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorsConfig {
+    /// Where procfs is mounted, e.g. `/host/proc` when running in a container
+    pub proc_path: PathBuf,
+    /// Where sysfs is mounted, e.g. `/host/sys` when running in a container
+    pub sys_path: PathBuf,
+    pub idle: IdleConfig,
+    pub cpu: SensorConfig,
+    pub load: SensorConfig,
+    pub memory: SensorConfig,
+    pub uptime: SensorConfig,
+    pub disk: DiskConfig,
+    pub network: NetworkConfig,
+    pub power: SensorConfig,
+    pub hwmon: SensorConfig,
+}
+
+impl Default for SensorsConfig {
+    fn default() -> Self {
+        Self {
+            proc_path: "/proc".into(),
+            sys_path: "/sys".into(),
+            idle: IdleConfig::default(),
+            cpu: SensorConfig::default(),
+            load: SensorConfig::default(),
+            memory: SensorConfig::default(),
+            uptime: SensorConfig::default(),
+            disk: DiskConfig::default(),
+            network: NetworkConfig::default(),
+            power: SensorConfig::default(),
+            hwmon: SensorConfig::default(),
+        }
+    }
+}
+
+impl SensorsConfig {
+    /// Name and interval of every sensor
+    fn intervals(&self) -> [(&'static str, u64); 9] {
+        [
+            ("idle", self.idle.interval),
+            ("cpu", self.cpu.interval),
+            ("load", self.load.interval),
+            ("memory", self.memory.interval),
+            ("uptime", self.uptime.interval),
+            ("disk", self.disk.interval),
+            ("network", self.network.interval),
+            ("power", self.power.interval),
+            ("hwmon", self.hwmon.interval),
+        ]
+    }
+}
+
+/// Settings for a sensor which is disabled unless configured
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorConfig {
+    pub enabled: bool,
+    /// Seconds between reads
+    pub interval: u64,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 10,
+        }
+    }
+}
+
+/// User idle time sensor settings
+///
+/// The thresholds are configured at the top level, as they can also be given on the command line.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    /// Seconds between reads
+    pub interval: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 1,
+        }
+    }
+}
+
+/// Disk usage sensor settings
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiskConfig {
+    pub enabled: bool,
+    /// Seconds between reads
+    pub interval: u64,
+    /// Mount points to report, all real filesystems if empty
+    pub include: Vec<String>,
+    /// Mount points to leave out
+    pub exclude: Vec<String>,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 60,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+/// Network interface sensor settings
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub enabled: bool,
+    /// Seconds between reads
+    pub interval: u64,
+    /// Interfaces to report, all if empty
+    pub include: Vec<String>,
+    /// Interfaces to leave out
+    pub exclude: Vec<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 10,
+            include: Vec::new(),
+            exclude: vec!["lo".into()],
+        }
+    }
+}
+
+/// Remote command settings
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandsConfig {
+    /// Subscribe to `<root>/<hostname>/command/#` and execute received commands
+    pub enabled: bool,
+    /// Accept commands from the primary broker, mirrors opt in with `commands`
+    pub primary: bool,
+    pub power: PowerConfig,
+    pub exec: ExecConfig,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            primary: true,
+            power: PowerConfig::default(),
+            exec: ExecConfig::default(),
+        }
+    }
+}
+
+/// Allowlisted commands which can be run through the `exec` command
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecConfig {
+    /// Maximum number of bytes of stdout and stderr each to publish
+    pub max_output: usize,
+    /// Commands by name
+    pub commands: BTreeMap<String, ExecCommandConfig>,
+}
+
+impl Default for ExecConfig {
+    fn default() -> Self {
+        Self {
+            max_output: 4096,
+            commands: BTreeMap::new(),
+        }
+    }
+}
+
+/// A command run without a shell
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecCommandConfig {
+    pub program: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub working_dir: Option<PathBuf>,
+    /// Seconds before the command is killed
+    #[serde(default = "ExecCommandConfig::default_timeout")]
+    pub timeout: u64,
+    /// Extra environment variables
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl ExecCommandConfig {
+    fn default_timeout() -> u64 {
+        60
+    }
+}
+
+impl core::fmt::Debug for ExecCommandConfig {
+    /// Environment variables often carry credentials, only show the names
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        fmt.debug_struct("ExecCommandConfig")
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .field("working_dir", &self.working_dir)
+            .field("timeout", &self.timeout)
+            .field("env", &self.env.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Power management command settings
+///
+/// Every action has to be enabled explicitly.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConfig {
+    pub backend: PowerBackendKind,
+    /// Seconds to wait before executing an action, unless the command gives a delay
+    pub delay: u64,
+    pub shutdown: bool,
+    pub reboot: bool,
+    pub suspend: bool,
+    pub hibernate: bool,
+    pub lock: bool,
+}
+
+/// How power actions are executed
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerBackendKind {
+    /// Operating system commands, e.g. `systemctl poweroff` or `shutdown /s`
+    #[default]
+    System,
+    /// Only log actions
+    DryRun,
+}
+
+/// Desktop notification settings
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Subscribe to `<root>/<hostname>/notify` and show received notifications
+    pub enabled: bool,
+    pub backend: NotifierKind,
+}
+
+/// How notifications are shown
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifierKind {
+    /// Desktop notification service
+    #[default]
+    Freedesktop,
+    /// Only log notifications
+    #[serde(alias = "recording")]
+    Log,
+}
+
+/// Default per-user configuration file location
+///
+/// - Linux: `$XDG_CONFIG_HOME/modo/config.toml` or `~/.config/modo/config.toml`
+/// - Windows: `%APPDATA%\modo\config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("modo").join("config.toml"))
+}
+
+/// MQTT server URL, with the password hidden from debug output
+#[derive(Deserialize, Default, Clone)]
+#[serde(transparent)]
+pub struct MqttUrl(String);
+
+impl MqttUrl {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl core::fmt::Debug for MqttUrl {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let url = &self.0;
+        let redacted = url
+            .split_once("://")
+            .and_then(|(scheme, rest)| {
+                let (userinfo, host) = rest.split_once('@')?;
+                let (user, _password) = userinfo.split_once(':')?;
+                Some(format!("{scheme}://{user}:***@{host}"))
+            })
+            .unwrap_or_else(|| url.clone());
+        write!(fmt, "{redacted:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// Configuration file unique to the test, removed when dropped
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("modo-test-{}-{name}.toml", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn args(config: &ConfigFile) -> Args {
+        Args {
+            config: Some(config.0.clone()),
+            mqtt_url: None,
+            threshold_active: None,
+            threshold_idle: None,
+            mqtt_root_topic: None,
+        }
+    }
+
+    fn valid() -> Config {
+        Config {
+            mqtt_url: MqttUrl("mqtt://localhost".into()),
+            ..Config::default()
+        }
+    }
+
+    fn config_error<T: core::fmt::Debug>(result: Result<T>) -> String {
+        match result {
+            Err(Error::Config(message)) => message,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let file = ConfigFile::new(
+            "file-overrides-defaults",
+            "mqtt_url = \"mqtt://file\"\nthreshold_idle = 600\n",
+        );
+        let config = Config::load(args(&file)).unwrap();
+        assert_eq!(config.mqtt_url.as_str(), "mqtt://file");
+        assert_eq!(config.threshold_idle, 600);
+        assert_eq!(config.threshold_active, 30);
+        assert_eq!(config.mqtt_root_topic, "modo");
+    }
+
+    #[test]
+    fn command_line_and_environment_override_file() {
+        let file = ConfigFile::new(
+            "precedence",
+            "mqtt_url = \"mqtt://file\"\nthreshold_active = 10\nthreshold_idle = 600\nmqtt_root_topic = \"file\"\n",
+        );
+        // SAFETY: this is the only test that parses `Args`, which reads these variables
+        unsafe {
+            std::env::set_var("MODO_THRESHOLD_IDLE", "700");
+            std::env::set_var("MODO_MQTT_ROOT_TOPIC", "env");
+        }
+        let parsed = Args::try_parse_from([
+            "modo".as_ref(),
+            "-c".as_ref(),
+            file.0.as_os_str(),
+            "-I".as_ref(),
+            "900".as_ref(),
+            "mqtt://cli".as_ref(),
+        ]);
+        unsafe {
+            std::env::remove_var("MODO_THRESHOLD_IDLE");
+            std::env::remove_var("MODO_MQTT_ROOT_TOPIC");
+        }
+        let config = Config::load(parsed.unwrap()).unwrap();
+        assert_eq!(config.mqtt_url.as_str(), "mqtt://cli");
+        assert_eq!(config.threshold_idle, 900);
+        assert_eq!(config.mqtt_root_topic, "env");
+        assert_eq!(config.threshold_active, 10);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let file = ConfigFile::new(
+            "unknown-field",
+            "mqtt_url = \"mqtt://file\"\ntreshold = 1\n",
+        );
+        assert!(matches!(Config::load(args(&file)), Err(Error::TomlDe(_))));
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let file = ConfigFile::new("missing", "");
+        let args = args(&file);
+        drop(file);
+        assert!(config_error(Config::load(args)).contains("unable to read"));
+    }
+
+    #[test]
+    fn validate_accepts_defaults_with_url() {
+        valid().validate().unwrap();
+    }
+
+    #[test]
+    fn validate_requires_url() {
+        let error = config_error(Config::default().validate());
+        assert!(error.starts_with("mqtt_url"));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_values() {
+        type Modify = fn(&mut Config);
+        let cases: [(&str, Modify); 8] = [
+            ("threshold_active", |c| {
+                c.threshold_active = c.threshold_idle
+            }),
+            ("mqtt_root_topic", |c| c.mqtt_root_topic = "modo/#".into()),
+            ("mqtt_root_topic", |c| c.mqtt_root_topic = "modo/".into()),
+            ("sensors.", |c| c.sensors.cpu.interval = 0),
+            ("mqtt.request_capacity", |c| c.mqtt.request_capacity = 0),
+            ("reconnect.initial_delay", |c| c.reconnect.initial_delay = 0),
+            ("reconnect.jitter", |c| c.reconnect.jitter = 1.5),
+            ("failover.attempts", |c| c.failover.attempts = 0),
+        ];
+        for (expected, modify) in cases {
+            let mut config = valid();
+            modify(&mut config);
+            let error = config_error(config.validate());
+            assert!(error.starts_with(expected), "{expected}: {error}");
+        }
+    }
+
+    #[test]
+    fn tls_fingerprint_is_parsed() {
+        let fingerprint = "46:4A:87:E3:19:48:DF:06:82:1F:CA:94:19:6F:0E:F2:F6:DE:0C:9D:C0:90:7B:9D:99:03:D0:DC:CB:FA:66:75";
+        let tls = TlsConfig {
+            fingerprint: Some(fingerprint.into()),
+            ..TlsConfig::default()
+        };
+        let bytes = tls.fingerprint("mqtt.tls").unwrap().unwrap();
+        assert_eq!(bytes[..3], [0x46, 0x4a, 0x87]);
+        assert_eq!(bytes[31], 0x75);
+        let tls = TlsConfig {
+            fingerprint: Some(fingerprint.replace(':', "").to_lowercase()),
+            ..TlsConfig::default()
+        };
+        assert_eq!(tls.fingerprint("mqtt.tls").unwrap(), Some(bytes));
+        assert_eq!(TlsConfig::default().fingerprint("mqtt.tls").unwrap(), None);
+    }
+
+    #[test]
+    fn tls_fingerprint_is_validated() {
+        let zeros = "00".repeat(32);
+        for fingerprint in [
+            "00".repeat(31),
+            "00".repeat(33),
+            format!("+0{}", &zeros[2..]),
+            format!("0g{}", &zeros[2..]),
+            format!("ü{}", &zeros[2..]),
+        ] {
+            let mut config = valid();
+            config.mqtt.tls.fingerprint = Some(fingerprint.clone());
+            let error = config_error(config.validate());
+            assert!(error.starts_with("mqtt.tls.fingerprint"), "{fingerprint}");
+        }
+        let mut config = valid();
+        config.mqtt.tls.fingerprint = Some(zeros);
+        config.validate().unwrap();
+        config.mqtt.tls.ca_file = Some("/etc/modo/ca.pem".into());
+        let error = config_error(config.validate());
+        assert!(error.contains("can't be combined"));
+    }
+
+    #[test]
+    fn validate_rejects_invalid_mirror() {
+        let mut config = valid();
+        config.mirrors.push(MirrorConfig::default());
+        let error = config_error(config.validate());
+        assert_eq!(error, "mirrors[0].url must be set");
+    }
+}
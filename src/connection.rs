@@ -0,0 +1,219 @@
+//! Connection state machine with jittered exponential backoff between attempts
+//!
+//! ```text
+//! Connecting -> Connected -> BackingOff -> Connecting -> ...
+//!                               \-> GivingUp once max_retries is exceeded
+//! ```
+//!
+//! The status is published on `<root>/<hostname>/connection` after every connect.
+
+use std::time::Duration;
+
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::Serialize;
+
+use crate::{Error, config::ReconnectConfig};
+
+pub const CONNECTION_TOPIC: &str = "connection";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    BackingOff,
+    GivingUp,
+}
+
+impl ConnectionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::BackingOff => "backing_off",
+            ConnectionState::GivingUp => "giving_up",
+        }
+    }
+}
+
+/// Connection state with the last error, for diagnostics
+#[derive(Serialize, Debug, Clone)]
+pub struct ConnectionStatus {
+    pub state: ConnectionState,
+    /// Failed attempts in a row, before the current connection if connected
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connected_at: Option<DateTime<Utc>>,
+}
+
+/// Tracks connection state and decides how long to wait before reconnecting
+pub struct ConnectionMonitor {
+    config: ReconnectConfig,
+    status: ConnectionStatus,
+    /// Appended to log lines, to tell the brokers apart
+    label: String,
+}
+
+impl ConnectionMonitor {
+    pub fn new(config: ReconnectConfig) -> Self {
+        Self {
+            config,
+            status: ConnectionStatus {
+                state: ConnectionState::Connecting,
+                attempts: 0,
+                last_error: None,
+                last_error_at: None,
+                connected_at: None,
+            },
+            label: String::new(),
+        }
+    }
+
+    /// Log state changes with the broker they belong to
+    pub fn with_broker(mut self, broker: &str) -> Self {
+        self.label = format!(" broker={broker}");
+        self
+    }
+
+    pub fn status(&self) -> &ConnectionStatus {
+        &self.status
+    }
+
+    /// Connection accepted by the broker
+    pub fn connected(&mut self) {
+        self.status.connected_at = Some(Utc::now().trunc_subsecs(0));
+        self.transition(ConnectionState::Connected);
+    }
+
+    /// Connection attempt failed or the connection was lost
+    ///
+    /// Returns how long to back off, or `None` when giving up.
+    pub fn failed(&mut self, error: &Error) -> Option<Duration> {
+        if self.status.state == ConnectionState::Connected {
+            self.status.attempts = 0;
+        }
+        self.status.attempts += 1;
+        self.status.last_error = Some(match error {
+            Error::Mqtt(e) => e.clone(),
+            e => e.to_string(),
+        });
+        self.status.last_error_at = Some(Utc::now().trunc_subsecs(0));
+        if self
+            .config
+            .max_retries
+            .is_some_and(|max_retries| self.status.attempts > max_retries)
+        {
+            self.transition(ConnectionState::GivingUp);
+            return None;
+        }
+        let delay = self.delay();
+        self.status.state = ConnectionState::BackingOff;
+        println!(
+            "mqtt_state={} attempts={} delay={:.1}s{}",
+            ConnectionState::BackingOff.as_str(),
+            self.status.attempts,
+            delay.as_secs_f64(),
+            self.label
+        );
+        Some(delay)
+    }
+
+    /// Backoff is over, the next attempt starts
+    pub fn retrying(&mut self) {
+        self.transition(ConnectionState::Connecting);
+    }
+
+    /// Exponential delay for the current attempt, with random jitter
+    fn delay(&self) -> Duration {
+        let exponent = self.status.attempts.saturating_sub(1).min(64) as i32;
+        let delay = (self.config.initial_delay as f64 * self.config.multiplier.powi(exponent))
+            .min(self.config.max_delay as f64);
+        let jitter = 1.0 + self.config.jitter * (fastrand::f64() * 2.0 - 1.0);
+        Duration::from_secs_f64(delay * jitter)
+    }
+
+    fn transition(&mut self, state: ConnectionState) {
+        self.status.state = state;
+        println!(
+            "mqtt_state={} attempts={}{}",
+            state.as_str(),
+            self.status.attempts,
+            self.label
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(jitter: f64, max_retries: Option<u32>) -> ConnectionMonitor {
+        ConnectionMonitor::new(ReconnectConfig {
+            initial_delay: 1,
+            max_delay: 10,
+            multiplier: 2.0,
+            jitter,
+            max_retries,
+        })
+    }
+
+    fn error() -> Error {
+        Error::Mqtt("Connection refused".into())
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max_delay() {
+        let mut monitor = monitor(0.0, None);
+        let delays = (0..6)
+            .map(|_| monitor.failed(&error()).unwrap().as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(monitor.status().state, ConnectionState::BackingOff);
+        assert_eq!(monitor.status().attempts, 6);
+    }
+
+    #[test]
+    fn backoff_restarts_after_connecting() {
+        let mut monitor = monitor(0.0, None);
+        monitor.failed(&error());
+        monitor.failed(&error());
+        monitor.retrying();
+        monitor.connected();
+        assert_eq!(monitor.status().state, ConnectionState::Connected);
+        assert_eq!(monitor.failed(&error()), Some(Duration::from_secs(1)));
+        assert_eq!(monitor.status().attempts, 1);
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let mut monitor = monitor(0.5, None);
+        for _ in 0..3 {
+            monitor.failed(&error());
+        }
+        // 4s with up to 50% either way
+        for _ in 0..100 {
+            let delay = monitor.delay();
+            assert!(
+                (Duration::from_secs(2)..=Duration::from_secs(6)).contains(&delay),
+                "{delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let mut monitor = monitor(0.0, Some(2));
+        assert!(monitor.failed(&error()).is_some());
+        assert!(monitor.failed(&error()).is_some());
+        assert_eq!(monitor.failed(&error()), None);
+        let status = monitor.status();
+        assert_eq!(status.state, ConnectionState::GivingUp);
+        assert_eq!(status.attempts, 3);
+        assert_eq!(status.last_error.as_deref(), Some("Connection refused"));
+        assert!(status.last_error_at.is_some());
+    }
+}
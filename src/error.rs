@@ -8,8 +8,24 @@ pub enum Error {
     Io(std::io::Error),
     #[from]
     MqttOption(rumqttc::OptionError),
+    Mqtt(String),
+    TlsFile(String),
+    TlsPem(String),
     #[from]
     OsString(std::ffi::OsString),
+    #[from]
+    TomlDe(toml::de::Error),
+    Config(String),
+    #[from]
+    UserIdle(user_idle::Error),
+    Sensor(String),
+    #[from]
+    Json(serde_json::Error),
+    Command(String),
+    #[from]
+    Notify(notify_rust::error::Error),
+    #[from]
+    Signal(ctrlc::Error),
 }
 
 impl std::error::Error for Error {}
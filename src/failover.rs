@@ -0,0 +1,177 @@
+//! Failover across a list of brokers
+//!
+//! After `attempts` failures in a row the next broker in the list is tried. While connected
+//! to a backup, the primary broker is probed in the background and the connection is closed
+//! once it's reachable again, so that the next connection goes back to the primary.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{net::TcpStream, task::JoinHandle, time};
+
+use crate::{config::FailoverConfig, mqtt, publisher::Publisher, shutdown::Shutdown};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Picks the broker to connect to
+pub struct Failover {
+    brokers: usize,
+    current: usize,
+    failures: u32,
+    attempts: u32,
+    check_interval: Duration,
+    primary: Option<(String, u16)>,
+    returning: Arc<AtomicBool>,
+    probe: Option<JoinHandle<()>>,
+}
+
+impl Failover {
+    /// `primary_url` is the first of `brokers` URLs
+    pub fn new(primary_url: &str, brokers: usize, config: &FailoverConfig) -> Self {
+        Self {
+            brokers,
+            current: 0,
+            failures: 0,
+            attempts: config.attempts,
+            check_interval: Duration::from_secs(config.primary_check_interval),
+            primary: mqtt::socket_address(primary_url),
+            returning: Arc::default(),
+            probe: None,
+        }
+    }
+
+    /// Connection accepted, start watching the primary if this is a backup
+    pub fn connected(&mut self, publisher: &Publisher, shutdown: &Shutdown) {
+        self.failures = 0;
+        if self.current == 0 || self.probe.is_some() {
+            return;
+        }
+        let Some(primary) = self.primary.clone() else {
+            return;
+        };
+        let check_interval = self.check_interval;
+        let returning = self.returning.clone();
+        let publisher = publisher.clone();
+        let shutdown = shutdown.clone();
+        self.probe = Some(tokio::spawn(async move {
+            while !shutdown.sleep(check_interval).await {
+                if reachable(&primary).await {
+                    println!("mqtt_primary_reachable={}:{}", primary.0, primary.1);
+                    returning.store(true, Ordering::SeqCst);
+                    publisher.publish_disconnected().await;
+                    if let Err(e) = publisher.client().disconnect().await {
+                        eprintln!("mqtt_disconnect_error={e}");
+                    }
+                    return;
+                }
+            }
+        }));
+    }
+
+    /// Connection attempt failed or the connection was lost
+    ///
+    /// Returns the broker to switch to, if it's time to move on.
+    pub fn failed(&mut self) -> Option<usize> {
+        self.stop_probe();
+        self.failures += 1;
+        if self.brokers < 2 || self.failures < self.attempts {
+            return None;
+        }
+        Some(self.switch((self.current + 1) % self.brokers))
+    }
+
+    /// Whether the connection was closed to go back to the primary broker
+    ///
+    /// Returns the primary broker's index if so.
+    pub fn take_return(&mut self) -> Option<usize> {
+        self.stop_probe();
+        self.returning
+            .swap(false, Ordering::SeqCst)
+            .then(|| self.switch(0))
+    }
+
+    fn switch(&mut self, index: usize) -> usize {
+        self.current = index;
+        self.failures = 0;
+        index
+    }
+
+    fn stop_probe(&mut self) {
+        if let Some(probe) = self.probe.take() {
+            probe.abort();
+        }
+    }
+}
+
+/// Whether a TCP connection to the address succeeds
+async fn reachable((host, port): &(String, u16)) -> bool {
+    matches!(
+        time::timeout(PROBE_TIMEOUT, TcpStream::connect((host.as_str(), *port))).await,
+        Ok(Ok(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failover(brokers: usize, attempts: u32) -> Failover {
+        let config = FailoverConfig {
+            attempts,
+            ..FailoverConfig::default()
+        };
+        Failover::new(
+            "mqtts://primary.example.com?client_id=modo",
+            brokers,
+            &config,
+        )
+    }
+
+    /// Results of `count` failures in a row
+    fn fail(failover: &mut Failover, count: usize) -> Vec<Option<usize>> {
+        (0..count).map(|_| failover.failed()).collect()
+    }
+
+    #[test]
+    fn primary_address_is_probed() {
+        let failover = failover(2, 3);
+        assert_eq!(failover.primary, Some(("primary.example.com".into(), 8883)));
+    }
+
+    #[test]
+    fn single_broker_never_switches() {
+        let mut failover = failover(1, 1);
+        assert!(fail(&mut failover, 10).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn switches_after_attempts_in_a_row() {
+        let mut failover = failover(3, 3);
+        assert_eq!(fail(&mut failover, 3), [None, None, Some(1)]);
+        assert_eq!(fail(&mut failover, 3), [None, None, Some(2)]);
+    }
+
+    #[test]
+    fn wraps_around_to_primary() {
+        let mut failover = failover(2, 1);
+        assert_eq!(fail(&mut failover, 4), [Some(1), Some(0), Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn take_return_goes_back_to_primary_once() {
+        let mut failover = failover(3, 2);
+        assert_eq!(failover.take_return(), None);
+        assert_eq!(fail(&mut failover, 4), [None, Some(1), None, Some(2)]);
+        failover.failed();
+        failover.returning.store(true, Ordering::SeqCst);
+        assert_eq!(failover.take_return(), Some(0));
+        assert_eq!(failover.take_return(), None);
+        // Failures on the previous broker don't count towards the primary
+        assert_eq!(fail(&mut failover, 2), [None, Some(1)]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Home Assistant MQTT discovery
+//!
+//! See <https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery>
+
+use rumqttc::QoS;
+use serde::Serialize;
+
+use crate::{
+    config::OutputMode,
+    mqtt::{MqttClient, Properties},
+    sensor::{Datatype, Metric, SensorInfo},
+};
+
+/// Discovery payload for a single entity
+#[derive(Serialize, Debug)]
+struct Entity<'a> {
+    name: &'a str,
+    unique_id: String,
+    object_id: String,
+    state_topic: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state_class: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_of_measurement: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_on: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_off: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    availability_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    availability_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_available: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload_not_available: Option<&'a str>,
+    device: &'a Device,
+}
+
+/// Device block shared by all entities of one host
+#[derive(Serialize, Debug)]
+struct Device {
+    identifiers: Vec<String>,
+    name: String,
+    manufacturer: &'static str,
+    model: &'static str,
+    sw_version: &'static str,
+}
+
+/// Home Assistant component an entity is announced as
+#[derive(Debug, Clone, Copy)]
+enum Component {
+    Sensor,
+    BinarySensor,
+}
+
+impl Component {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::BinarySensor => "binary_sensor",
+        }
+    }
+}
+
+/// Publish retained discovery config for all sensor metrics of this host
+pub async fn publish_discovery(
+    client: &MqttClient,
+    prefix: &str,
+    hostname: &str,
+    base_topic: &str,
+    mode: OutputMode,
+    sensors: &[SensorInfo],
+) {
+    let node_id = object_id(hostname);
+    let device = Device {
+        identifiers: vec![format!("modo_{node_id}")],
+        name: hostname.to_string(),
+        manufacturer: "modo",
+        model: env!("CARGO_PKG_DESCRIPTION"),
+        sw_version: env!("CARGO_PKG_VERSION"),
+    };
+    let (availability, available, not_available) = mode.availability();
+    let availability_topic = format!("{base_topic}/{availability}");
+    // Homie has more states than ready and lost, e.g. disconnected after a clean shutdown
+    let availability_template = match mode {
+        OutputMode::Plain => None,
+        OutputMode::Homie => Some(format!(
+            "{{{{ '{available}' if value == '{available}' else '{not_available}' }}}}"
+        )),
+    };
+
+    let mut entities = Vec::new();
+    for sensor in sensors {
+        for metric in &sensor.metrics {
+            let object = object_id(&metric.id);
+            let state_topic = format!(
+                "{base_topic}/{}",
+                mode.metric_path(&sensor.name, &metric.id)
+            );
+            let entity = metric_entity(metric, &node_id, &object, state_topic, &device);
+            let component = match metric.datatype {
+                Datatype::Boolean => Component::BinarySensor,
+                _ => Component::Sensor,
+            };
+            entities.push((
+                component,
+                object,
+                Entity {
+                    availability_topic: Some(availability_topic.clone()),
+                    availability_template: availability_template.clone(),
+                    payload_available: Some(available),
+                    payload_not_available: Some(not_available),
+                    ..entity
+                },
+            ));
+        }
+    }
+    entities.push((
+        Component::BinarySensor,
+        "connected".to_string(),
+        Entity {
+            name: "Connected",
+            unique_id: format!("modo_{node_id}_connected"),
+            object_id: format!("{node_id}_connected"),
+            state_topic: availability_topic.clone(),
+            device_class: Some("connectivity"),
+            state_class: None,
+            unit_of_measurement: None,
+            options: None,
+            payload_on: Some(available),
+            payload_off: Some(not_available),
+            value_template: availability_template,
+            availability_topic: None,
+            availability_template: None,
+            payload_available: None,
+            payload_not_available: None,
+            device: &device,
+        },
+    ));
+
+    for (component, object, entity) in entities {
+        let topic = format!("{prefix}/{}/{node_id}/{object}/config", component.as_str());
+        let payload = match serde_json::to_string(&entity) {
+            Ok(payload) => payload,
+            Err(e) => {
+                eprintln!("homeassistant_discovery_{object}_error={e}");
+                continue;
+            }
+        };
+        if let Err(e) = client
+            .publish(
+                &topic,
+                QoS::AtLeastOnce,
+                true,
+                payload.into(),
+                Properties::json(),
+            )
+            .await
+        {
+            eprintln!("homeassistant_discovery_{object}_error={e}");
+        }
+    }
+}
+
+/// Entity describing a single sensor metric
+fn metric_entity<'a>(
+    metric: &'a Metric,
+    node_id: &str,
+    object: &str,
+    state_topic: String,
+    device: &'a Device,
+) -> Entity<'a> {
+    let (device_class, state_class, options, payload_on, payload_off) = match &metric.datatype {
+        Datatype::Integer | Datatype::Float if metric.counter => {
+            (None, Some("total_increasing"), None, None, None)
+        }
+        Datatype::Integer | Datatype::Float => (None, Some("measurement"), None, None, None),
+        Datatype::Boolean => (None, None, None, Some("true"), Some("false")),
+        Datatype::String => (None, None, None, None, None),
+        Datatype::Enum(options) => (Some("enum"), None, Some(options.as_slice()), None, None),
+        Datatype::Datetime => (Some("timestamp"), None, None, None, None),
+    };
+    Entity {
+        name: &metric.name,
+        unique_id: format!("modo_{node_id}_{object}"),
+        object_id: format!("{node_id}_{object}"),
+        state_topic,
+        device_class: metric.device_class.or(device_class),
+        state_class,
+        unit_of_measurement: metric.unit.as_deref(),
+        options,
+        payload_on,
+        payload_off,
+        value_template: None,
+        availability_topic: None,
+        availability_template: None,
+        payload_available: None,
+        payload_not_available: None,
+        device,
+    }
+}
+
+/// Home Assistant only accepts `[a-zA-Z0-9_-]` in node and object ids
+fn object_id(id: &str) -> String {
+    id.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+//! Homie 4 convention output
+//!
+//! The `<root>/<hostname>` base topic becomes a Homie device with one node per sensor.
+//! See <https://homieiot.github.io/specification/spec-core-v4_0_0/>
+
+use rumqttc::QoS;
+
+use crate::{
+    mqtt::{MqttClient, Properties, Will},
+    sensor::{Datatype, SensorInfo},
+};
+
+/// Topic (relative to the device) a metric is published on
+///
+/// Homie ids only allow lowercase letters, digits and hyphens, and can't start
+/// with a hyphen. A metric id prefixed with the sensor name (e.g. `cpu/usage`)
+/// drops the prefix.
+pub fn property_path(sensor: &str, metric: &str) -> String {
+    format!("{}/{}", homie_id(sensor), property_id(sensor, metric))
+}
+
+fn property_id(sensor: &str, metric: &str) -> String {
+    let metric = metric
+        .strip_prefix(sensor)
+        .and_then(|m| m.strip_prefix('/'))
+        .unwrap_or(metric);
+    homie_id(metric)
+}
+
+fn homie_id(id: &str) -> String {
+    let id: String = id
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect();
+    id.trim_start_matches('-').to_string()
+}
+
+/// Last will marking the device as lost
+pub fn last_will(base_topic: &str) -> Will {
+    Will {
+        topic: format!("{base_topic}/$state"),
+        payload: "lost".into(),
+        qos: QoS::AtLeastOnce,
+        retain: true,
+    }
+}
+
+/// State of a device which disconnected cleanly
+pub const DISCONNECTED: &str = "disconnected";
+
+/// Publish device, node and property attributes, then mark the device as ready
+pub async fn publish_device(
+    client: &MqttClient,
+    hostname: &str,
+    base_topic: &str,
+    sensors: &[SensorInfo],
+) {
+    let nodes = sensors
+        .iter()
+        .map(|s| homie_id(&s.name))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut attributes = vec![
+        ("$state".to_string(), "init".to_string()),
+        ("$homie".to_string(), "4.0.0".to_string()),
+        ("$name".to_string(), hostname.to_string()),
+        ("$extensions".to_string(), String::new()),
+        ("$nodes".to_string(), nodes),
+    ];
+    for sensor in sensors {
+        let node = homie_id(&sensor.name);
+        let properties = sensor
+            .metrics
+            .iter()
+            .map(|m| property_id(&sensor.name, &m.id))
+            .collect::<Vec<_>>()
+            .join(",");
+        attributes.push((format!("{node}/$name"), sensor.name.clone()));
+        attributes.push((format!("{node}/$type"), sensor.name.clone()));
+        attributes.push((format!("{node}/$properties"), properties));
+        for metric in &sensor.metrics {
+            let path = property_path(&sensor.name, &metric.id);
+            let (datatype, format) = match &metric.datatype {
+                Datatype::Integer => ("integer", None),
+                Datatype::Float => ("float", None),
+                Datatype::Boolean => ("boolean", None),
+                Datatype::String => ("string", None),
+                Datatype::Enum(options) => ("enum", Some(options.join(","))),
+                Datatype::Datetime => ("datetime", None),
+            };
+            attributes.push((format!("{path}/$name"), metric.name.clone()));
+            attributes.push((format!("{path}/$datatype"), datatype.to_string()));
+            if let Some(unit) = &metric.unit {
+                attributes.push((format!("{path}/$unit"), unit.clone()));
+            }
+            if let Some(format) = format {
+                attributes.push((format!("{path}/$format"), format));
+            }
+        }
+    }
+    attributes.push(("$state".to_string(), "ready".to_string()));
+    for (topic, payload) in attributes {
+        if let Err(e) = client
+            .publish(
+                &format!("{base_topic}/{topic}"),
+                QoS::AtLeastOnce,
+                true,
+                payload.into(),
+                Properties::text(),
+            )
+            .await
+        {
+            eprintln!("homie_publish_{topic}_error={e}");
+        }
+    }
+}
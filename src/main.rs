@@ -1,6 +1,7 @@
 use modo::Result;
 
-fn main() -> Result<()> {
+#[tokio::main]
+async fn main() -> Result<()> {
     let args = wild::args_os();
-    modo::run(args)
+    modo::run(args).await
 }
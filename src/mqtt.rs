@@ -0,0 +1,657 @@
+//! MQTT client and connection over either protocol version
+//!
+//! MQTT 3.1.1 uses [`rumqttc::AsyncClient`], MQTT 5 uses [`rumqttc::v5::AsyncClient`].
+//! Properties only available in MQTT 5 are ignored with MQTT 3.1.1.
+
+use std::{fs, io::BufReader, path::Path, sync::Arc};
+
+use http::{HeaderName, HeaderValue, Request};
+use rumqttc::{
+    ConnectionError, Event, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport,
+    tokio_rustls::rustls::{
+        self, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+        client::{
+            WantsClientCert,
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        },
+        crypto::WebPkiSupportedAlgorithms,
+        pki_types::{CertificateDer, ServerName, UnixTime},
+    },
+    v5::{
+        self,
+        mqttbytes::v5::{
+            Filter, LastWillProperties, Packet as PacketV5, PublishProperties, RetainForwardRule,
+        },
+    },
+};
+
+use crate::{
+    Error, Result,
+    config::{MqttVersion, TlsConfig, WebSocketConfig},
+};
+
+/// Message the broker publishes when the connection is lost
+#[derive(Debug, Clone)]
+pub struct Will {
+    pub topic: String,
+    pub payload: String,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// MQTT 5 properties attached to a publish
+#[derive(Debug, Clone, Default)]
+pub struct Properties {
+    pub content_type: Option<String>,
+    /// Seconds before the broker discards the message
+    pub message_expiry: Option<u32>,
+    pub response_topic: Option<String>,
+    pub correlation_data: Option<Vec<u8>>,
+    /// Sent next to the user properties of the client
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl Properties {
+    /// Plain text payload
+    pub fn text() -> Self {
+        Self {
+            content_type: Some("text/plain".into()),
+            ..Default::default()
+        }
+    }
+
+    /// JSON payload
+    pub fn json() -> Self {
+        Self {
+            content_type: Some("application/json".into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Message received on a subscribed topic
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    /// Retained by the broker rather than published just now
+    pub retain: bool,
+    /// MQTT 5 response topic
+    pub response_topic: Option<String>,
+    /// MQTT 5 correlation data
+    pub correlation_data: Option<Vec<u8>>,
+}
+
+/// Event of interest from the connection
+#[derive(Debug)]
+pub enum Notification {
+    /// Connection accepted by the broker
+    Connected { code: String, session_present: bool },
+    /// Message received on a subscribed topic
+    Message(Message),
+    /// Acks and pings
+    Routine,
+    /// Any other packet received
+    Incoming(String),
+    /// Any other packet sent
+    Outgoing(String),
+    /// Disconnect sent, no more messages go out
+    Disconnected,
+}
+
+/// Handle to publish and subscribe, cheap to clone
+#[derive(Clone)]
+pub enum MqttClient {
+    V4(rumqttc::AsyncClient),
+    V5 {
+        client: v5::AsyncClient,
+        /// Attached to every publish
+        user_properties: Vec<(String, String)>,
+    },
+}
+
+/// Event loop of the client, which has to be polled to make progress
+pub enum MqttConnection {
+    V4(Box<rumqttc::EventLoop>),
+    V5(Box<v5::EventLoop>),
+}
+
+impl MqttClient {
+    /// Create client from the options parsed from the MQTT URL
+    pub fn new(
+        options: MqttOptions,
+        version: MqttVersion,
+        will: Will,
+        user_properties: Vec<(String, String)>,
+        cap: usize,
+    ) -> (MqttClient, MqttConnection) {
+        match version {
+            MqttVersion::V311 => {
+                let mut options = options;
+                options.set_last_will(rumqttc::LastWill::new(
+                    will.topic,
+                    will.payload,
+                    will.qos,
+                    will.retain,
+                ));
+                let (client, connection) = rumqttc::AsyncClient::new(options, cap);
+                (
+                    MqttClient::V4(client),
+                    MqttConnection::V4(Box::new(connection)),
+                )
+            }
+            MqttVersion::V5 => {
+                let (host, port) = options.broker_address();
+                let mut options_v5 = v5::MqttOptions::new(options.client_id(), host, port);
+                options_v5
+                    .set_transport(options.transport())
+                    .set_keep_alive(options.keep_alive())
+                    .set_clean_start(options.clean_session())
+                    .set_request_channel_capacity(options.request_channel_capacity())
+                    .set_pending_throttle(options.pending_throttle())
+                    .set_user_properties(user_properties.clone())
+                    .set_last_will(v5::mqttbytes::v5::LastWill::new(
+                        will.topic,
+                        will.payload,
+                        qos_v5(will.qos),
+                        will.retain,
+                        Some(LastWillProperties {
+                            delay_interval: None,
+                            payload_format_indicator: None,
+                            message_expiry_interval: None,
+                            content_type: Some("text/plain".into()),
+                            response_topic: None,
+                            correlation_data: None,
+                            user_properties: user_properties.clone(),
+                        }),
+                    ));
+                if let Some((username, password)) = options.credentials() {
+                    options_v5.set_credentials(username, password);
+                }
+                if let Some(modifier) = options.request_modifier() {
+                    options_v5.set_request_modifier(move |request| modifier(request));
+                }
+                let (client, connection) = v5::AsyncClient::new(options_v5, cap);
+                (
+                    MqttClient::V5 {
+                        client,
+                        user_properties,
+                    },
+                    MqttConnection::V5(Box::new(connection)),
+                )
+            }
+        }
+    }
+
+    /// Publish, waiting while the request queue is full
+    pub async fn publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: Vec<u8>,
+        properties: Properties,
+    ) -> Result<()> {
+        match self {
+            MqttClient::V4(client) => client
+                .publish(topic, qos, retain, payload)
+                .await
+                .map_err(|e| Error::Mqtt(e.to_string())),
+            MqttClient::V5 {
+                client,
+                user_properties,
+            } => client
+                .publish_with_properties(
+                    topic,
+                    qos_v5(qos),
+                    retain,
+                    payload,
+                    publish_properties(user_properties, properties),
+                )
+                .await
+                .map_err(|e| Error::Mqtt(e.to_string())),
+        }
+    }
+
+    /// Publish without waiting, fails when the request queue is full
+    pub fn try_publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        retain: bool,
+        payload: Vec<u8>,
+        properties: Properties,
+    ) -> Result<()> {
+        match self {
+            MqttClient::V4(client) => client
+                .try_publish(topic, qos, retain, payload)
+                .map_err(|e| Error::Mqtt(e.to_string())),
+            MqttClient::V5 {
+                client,
+                user_properties,
+            } => client
+                .try_publish_with_properties(
+                    topic,
+                    qos_v5(qos),
+                    retain,
+                    payload,
+                    publish_properties(user_properties, properties),
+                )
+                .map_err(|e| Error::Mqtt(e.to_string())),
+        }
+    }
+
+    /// Disconnect cleanly after the messages already queued, so the last will isn't sent
+    pub async fn disconnect(&self) -> Result<()> {
+        match self {
+            MqttClient::V4(client) => client
+                .disconnect()
+                .await
+                .map_err(|e| Error::Mqtt(e.to_string())),
+            MqttClient::V5 { client, .. } => client
+                .disconnect()
+                .await
+                .map_err(|e| Error::Mqtt(e.to_string())),
+        }
+    }
+
+    /// Subscribe to a topic, with MQTT 5 asking the broker not to send retained messages
+    pub async fn subscribe(&self, topic: &str, qos: QoS) -> Result<()> {
+        match self {
+            MqttClient::V4(client) => client
+                .subscribe(topic, qos)
+                .await
+                .map_err(|e| Error::Mqtt(e.to_string())),
+            MqttClient::V5 { client, .. } => client
+                .subscribe_many([Filter {
+                    retain_forward_rule: RetainForwardRule::Never,
+                    ..Filter::new(topic, qos_v5(qos))
+                }])
+                .await
+                .map_err(|e| Error::Mqtt(e.to_string())),
+        }
+    }
+}
+
+impl MqttConnection {
+    /// Next event, `None` once all clients are gone
+    ///
+    /// After an error the next poll reconnects.
+    pub async fn poll(&mut self) -> Option<Result<Notification>> {
+        match self {
+            MqttConnection::V4(connection) => {
+                let event = match connection.poll().await {
+                    Ok(event) => event,
+                    Err(ConnectionError::RequestsDone) => return None,
+                    Err(e) => return Some(Err(Error::Mqtt(e.to_string()))),
+                };
+                Some(Ok(match event {
+                    Event::Incoming(p) => match p {
+                        Packet::ConnAck(c) => Notification::Connected {
+                            code: format!("{:?}", c.code),
+                            session_present: c.session_present,
+                        },
+                        Packet::Publish(p) => Notification::Message(Message {
+                            topic: p.topic,
+                            payload: p.payload.to_vec(),
+                            retain: p.retain,
+                            response_topic: None,
+                            correlation_data: None,
+                        }),
+                        Packet::SubAck(_) | Packet::PubAck(_) | Packet::PingResp => {
+                            Notification::Routine
+                        }
+                        p => Notification::Incoming(format!("{p:?}")),
+                    },
+                    Event::Outgoing(o) => outgoing(o),
+                }))
+            }
+            MqttConnection::V5(connection) => {
+                let event = match connection.poll().await {
+                    Ok(event) => event,
+                    Err(v5::ConnectionError::RequestsDone) => return None,
+                    Err(e) => return Some(Err(Error::Mqtt(e.to_string()))),
+                };
+                Some(Ok(match event {
+                    v5::Event::Incoming(p) => match p {
+                        PacketV5::ConnAck(c) => Notification::Connected {
+                            code: format!("{:?}", c.code),
+                            session_present: c.session_present,
+                        },
+                        PacketV5::Publish(p) => {
+                            let properties = p.properties.unwrap_or_default();
+                            Notification::Message(Message {
+                                topic: String::from_utf8_lossy(&p.topic).into_owned(),
+                                payload: p.payload.to_vec(),
+                                retain: p.retain,
+                                response_topic: properties.response_topic,
+                                correlation_data: properties.correlation_data.map(|c| c.to_vec()),
+                            })
+                        }
+                        PacketV5::SubAck(_) | PacketV5::PubAck(_) | PacketV5::PingResp(_) => {
+                            Notification::Routine
+                        }
+                        p => Notification::Incoming(format!("{p:?}")),
+                    },
+                    v5::Event::Outgoing(o) => outgoing(o),
+                }))
+            }
+        }
+    }
+}
+
+/// MQTT 5 publish properties, with the user properties of the client first
+fn publish_properties(
+    user_properties: &[(String, String)],
+    properties: Properties,
+) -> PublishProperties {
+    PublishProperties {
+        content_type: properties.content_type,
+        message_expiry_interval: properties.message_expiry,
+        response_topic: properties.response_topic,
+        correlation_data: properties.correlation_data.map(Into::into),
+        user_properties: user_properties
+            .iter()
+            .cloned()
+            .chain(properties.user_properties)
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Connection options for an MQTT URL, with the TLS and WebSocket settings applied
+///
+/// `name` is the configuration path of the broker settings, e.g. `mqtt` or `mirrors[0]`.
+pub fn options(
+    mqtt_url: &str,
+    tls: &TlsConfig,
+    websocket: &WebSocketConfig,
+    name: &str,
+) -> Result<MqttOptions> {
+    let options = MqttOptions::parse_url(mqtt_url)?;
+    let mut options = configure_websocket(options, mqtt_url, websocket, name)?;
+    configure_tls(&mut options, tls, name)?;
+    Ok(options)
+}
+
+/// Host and port a TCP connection to the broker goes to
+pub fn socket_address(mqtt_url: &str) -> Option<(String, u16)> {
+    let url = url::Url::parse(mqtt_url).ok()?;
+    let port = match (url.port(), url.scheme()) {
+        (Some(port), _) => port,
+        (None, "mqtt" | "tcp") => 1883,
+        (None, "mqtts" | "ssl") => 8883,
+        (None, _) => url.port_or_known_default()?,
+    };
+    Some((url.host_str()?.to_string(), port))
+}
+
+/// Use the CA or pinned fingerprint, client certificate and ALPN settings for an `mqtts://` connection
+fn configure_tls(options: &mut MqttOptions, config: &TlsConfig, name: &str) -> Result<()> {
+    if !config.is_custom() {
+        return Ok(());
+    }
+    let websocket = match options.transport() {
+        Transport::Tls(_) => false,
+        Transport::Wss(_) => true,
+        _ => {
+            return Err(Error::Config(format!(
+                "{name}.tls needs an mqtts:// or wss:// URL"
+            )));
+        }
+    };
+    let client_auth = match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => Some((
+            read_pem(cert, PemKind::Certificate)?,
+            read_pem(key, PemKind::PrivateKey)?,
+        )),
+        _ => None,
+    };
+    let alpn = (!config.alpn.is_empty()).then(|| {
+        config
+            .alpn
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect()
+    });
+    let tls = match (&config.ca_file, config.fingerprint(&format!("{name}.tls"))?) {
+        (Some(path), _) => TlsConfiguration::Simple {
+            ca: read_pem(path, PemKind::Certificate)?,
+            alpn,
+            client_auth,
+        },
+        (None, Some(fingerprint)) => {
+            let verifier = PinnedCertVerifier::new(fingerprint);
+            let builder = ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(verifier));
+            rustls_tls(builder, alpn, client_auth)?
+        }
+        (None, None) => platform_tls(alpn, client_auth)?,
+    };
+    options.set_transport(match websocket {
+        true => Transport::Wss(tls),
+        false => Transport::Tls(tls),
+    });
+    Ok(())
+}
+
+/// TLS trusting the platform root certificates, with a client certificate or ALPN
+fn platform_tls(
+    alpn: Option<Vec<Vec<u8>>>,
+    client_auth: Option<(Vec<u8>, Vec<u8>)>,
+) -> Result<TlsConfiguration> {
+    let mut roots = RootCertStore::empty();
+    let (added, _ignored) =
+        roots.add_parsable_certificates(rustls_native_certs::load_native_certs()?);
+    if added == 0 {
+        return Err(Error::TlsFile("no platform root certificates found".into()));
+    }
+    rustls_tls(
+        ClientConfig::builder().with_root_certificates(roots),
+        alpn,
+        client_auth,
+    )
+}
+
+/// Finish a rustls configuration with a client certificate or ALPN
+fn rustls_tls(
+    builder: rustls::ConfigBuilder<ClientConfig, WantsClientCert>,
+    alpn: Option<Vec<Vec<u8>>>,
+    client_auth: Option<(Vec<u8>, Vec<u8>)>,
+) -> Result<TlsConfiguration> {
+    let mut tls = match client_auth {
+        Some((cert, key)) => {
+            let certs = rustls_pemfile::certs(&mut cert.as_slice())
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let key = rustls_pemfile::private_key(&mut key.as_slice())?
+                .ok_or_else(|| Error::TlsPem("no PEM private key".into()))?;
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| Error::TlsPem(e.to_string()))?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    tls.alpn_protocols = alpn.unwrap_or_default();
+    Ok(TlsConfiguration::Rustls(Arc::new(tls)))
+}
+
+/// Accepts only the server certificate with the configured SHA-256 fingerprint
+///
+/// The certificate isn't checked against any CA, host name or validity period,
+/// but the handshake signature still proves the server holds its private key.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: [u8; 32],
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PinnedCertVerifier {
+    fn new(fingerprint: [u8; 32]) -> Self {
+        Self {
+            fingerprint,
+            algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let digest = ring::digest::digest(&ring::digest::SHA256, end_entity);
+        match digest.as_ref() == self.fingerprint {
+            true => Ok(ServerCertVerified::assertion()),
+            false => Err(rustls::Error::General(
+                "server certificate doesn't match the pinned fingerprint".into(),
+            )),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// Point a `ws://` or `wss://` connection at the full URL and add the configured headers
+///
+/// The URL parser only keeps the host, while the WebSocket transport expects
+/// the URL including the path as broker address.
+fn configure_websocket(
+    options: MqttOptions,
+    mqtt_url: &str,
+    config: &WebSocketConfig,
+    name: &str,
+) -> Result<MqttOptions> {
+    if !matches!(options.transport(), Transport::Ws | Transport::Wss(_)) {
+        if !config.headers.is_empty() {
+            return Err(Error::Config(format!(
+                "{name}.websocket needs a ws:// or wss:// URL"
+            )));
+        }
+        return Ok(options);
+    }
+    let url = url::Url::parse(mqtt_url).map_err(|e| Error::Config(e.to_string()))?;
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let broker_addr = format!("{}://{host}:{port}{}", url.scheme(), url.path());
+    let headers = config
+        .headers
+        .iter()
+        .map(|(header, value)| {
+            let invalid = |e: &dyn std::fmt::Display| {
+                Error::Config(format!("{name}.websocket.headers {header:?}: {e}"))
+            };
+            Ok((
+                HeaderName::from_bytes(header.as_bytes()).map_err(|e| invalid(&e))?,
+                HeaderValue::from_str(value).map_err(|e| invalid(&e))?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut websocket = MqttOptions::new(options.client_id(), broker_addr, port);
+    websocket
+        .set_transport(options.transport())
+        .set_keep_alive(options.keep_alive())
+        .set_clean_session(options.clean_session())
+        .set_request_channel_capacity(options.request_channel_capacity())
+        .set_pending_throttle(options.pending_throttle())
+        .set_inflight(options.inflight())
+        .set_manual_acks(options.manual_acks());
+    if let Some((username, password)) = options.credentials() {
+        websocket.set_credentials(username, password);
+    }
+    // There's no getter for the outgoing limit, take both from the URL like `parse_url` does
+    let packet_size = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .and_then(|(_, value)| value.parse::<usize>().ok())
+    };
+    if let (Some(incoming), Some(outgoing)) = (
+        packet_size("max_incoming_packet_size_bytes"),
+        packet_size("max_outgoing_packet_size_bytes"),
+    ) {
+        websocket.set_max_packet_size(incoming, outgoing);
+    }
+    if !headers.is_empty() {
+        websocket.set_request_modifier(move |mut request: Request<()>| {
+            for (name, value) in &headers {
+                request.headers_mut().insert(name.clone(), value.clone());
+            }
+            std::future::ready(request)
+        });
+    }
+    Ok(websocket)
+}
+
+enum PemKind {
+    Certificate,
+    PrivateKey,
+}
+
+/// Read PEM file, ensuring it holds at least one item of the expected kind
+fn read_pem(path: &Path, kind: PemKind) -> Result<Vec<u8>> {
+    let pem = fs::read(path)
+        .map_err(|e| Error::TlsFile(format!("unable to read {}: {e}", path.display())))?;
+    let mut reader = BufReader::new(pem.as_slice());
+    let found = match kind {
+        PemKind::Certificate => rustls_pemfile::certs(&mut reader)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map(|certs| !certs.is_empty()),
+        PemKind::PrivateKey => rustls_pemfile::private_key(&mut reader).map(|key| key.is_some()),
+    };
+    let expected = match kind {
+        PemKind::Certificate => "certificate",
+        PemKind::PrivateKey => "private key",
+    };
+    match found {
+        Ok(true) => Ok(pem),
+        Ok(false) => Err(Error::TlsPem(format!(
+            "no PEM {expected} in {}",
+            path.display()
+        ))),
+        Err(e) => Err(Error::TlsPem(format!(
+            "invalid PEM {expected} in {}: {e}",
+            path.display()
+        ))),
+    }
+}
+
+fn qos_v5(qos: QoS) -> v5::mqttbytes::QoS {
+    match qos {
+        QoS::AtMostOnce => v5::mqttbytes::QoS::AtMostOnce,
+        QoS::AtLeastOnce => v5::mqttbytes::QoS::AtLeastOnce,
+        QoS::ExactlyOnce => v5::mqttbytes::QoS::ExactlyOnce,
+    }
+}
+
+fn outgoing(outgoing: Outgoing) -> Notification {
+    match outgoing {
+        Outgoing::Publish(_) | Outgoing::PingReq => Notification::Routine,
+        Outgoing::Disconnect => Notification::Disconnected,
+        o => Notification::Outgoing(format!("{o:?}")),
+    }
+}
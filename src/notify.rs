@@ -0,0 +1,223 @@
+//! Desktop notifications received on `<root>/<hostname>/notify`
+//!
+//! The payload is a JSON object, e.g.
+//! `{"title": "Backup", "body": "Done", "urgency": "low", "timeout": 5000}`.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{Result, config::NotifierKind, mqtt::Message};
+
+/// Topic level below the base topic notifications are received on
+pub const NOTIFY_TOPIC: &str = "notify";
+
+/// Notification to show
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Notification {
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub urgency: Urgency,
+    /// Milliseconds before the notification is hidden, server default if not given
+    pub timeout: Option<u32>,
+}
+
+impl Notification {
+    pub fn parse(payload: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+/// Shows notifications
+pub trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Desktop notifications through the freedesktop notification service
+/// (or the platform equivalent)
+pub struct FreedesktopNotifier;
+
+impl Notifier for FreedesktopNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        let mut desktop = notify_rust::Notification::new();
+        desktop
+            .appname("modo")
+            .summary(&notification.title)
+            .body(&notification.body);
+        #[cfg(any(windows, all(unix, not(target_os = "macos"))))]
+        desktop.urgency(match notification.urgency {
+            Urgency::Low => notify_rust::Urgency::Low,
+            Urgency::Normal => notify_rust::Urgency::Normal,
+            Urgency::Critical => notify_rust::Urgency::Critical,
+        });
+        if let Some(timeout) = notification.timeout {
+            desktop.timeout(notify_rust::Timeout::Milliseconds(timeout));
+        }
+        desktop.show()?;
+        Ok(())
+    }
+}
+
+/// Only logs notifications, for headless machines
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        println!("notify={notification:?}");
+        Ok(())
+    }
+}
+
+/// Parses notification payloads and passes them to a notifier
+pub struct Notify {
+    topic: String,
+    notifier: Arc<dyn Notifier>,
+}
+
+impl Notify {
+    pub fn new(base_topic: &str, kind: NotifierKind) -> Self {
+        let notifier: Arc<dyn Notifier> = match kind {
+            NotifierKind::Freedesktop => Arc::new(FreedesktopNotifier),
+            NotifierKind::Log => Arc::new(LogNotifier),
+        };
+        Self::with_notifier(base_topic, notifier)
+    }
+
+    pub fn with_notifier(base_topic: &str, notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            topic: format!("{base_topic}/{NOTIFY_TOPIC}"),
+            notifier,
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Show the notification in the payload
+    pub fn handle(&self, message: &Message) {
+        // A retained notification would pop up again on every connect
+        if message.retain {
+            eprintln!("notify_error=retained notifications are ignored");
+            return;
+        }
+        let result = Notification::parse(&message.payload)
+            .and_then(|notification| self.notifier.notify(&notification));
+        if let Err(e) = result {
+            eprintln!("notify_error={e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Keeps notifications for inspection
+    #[derive(Default)]
+    struct RecordingNotifier {
+        notifications: Mutex<Vec<Notification>>,
+    }
+
+    impl RecordingNotifier {
+        fn notifications(&self) -> Vec<Notification> {
+            self.notifications.lock().unwrap().clone()
+        }
+    }
+
+    impl Notifier for RecordingNotifier {
+        fn notify(&self, notification: &Notification) -> Result<()> {
+            self.notifications
+                .lock()
+                .unwrap()
+                .push(notification.clone());
+            Ok(())
+        }
+    }
+
+    fn message(payload: &str, retain: bool) -> Message {
+        Message {
+            topic: "modo/myhost/notify".into(),
+            payload: payload.into(),
+            retain,
+            response_topic: None,
+            correlation_data: None,
+        }
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let notification = Notification::parse(br#"{"title": "Backup"}"#).unwrap();
+        assert_eq!(
+            notification,
+            Notification {
+                title: "Backup".into(),
+                body: String::new(),
+                urgency: Urgency::Normal,
+                timeout: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_all_fields() {
+        let payload = br#"{"title": "Backup", "body": "Done", "urgency": "low", "timeout": 5000}"#;
+        let notification = Notification::parse(payload).unwrap();
+        assert_eq!(notification.body, "Done");
+        assert_eq!(notification.urgency, Urgency::Low);
+        assert_eq!(notification.timeout, Some(5000));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_fields() {
+        assert!(Notification::parse(br#"{"title": "Backup", "icon": "disk"}"#).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_invalid_urgency() {
+        assert!(Notification::parse(br#"{"title": "Backup", "urgency": "urgent"}"#).is_err());
+    }
+
+    #[test]
+    fn handle_passes_notification_to_notifier() {
+        let recorder = Arc::new(RecordingNotifier::default());
+        let notify = Notify::with_notifier("modo/myhost", recorder.clone());
+        assert_eq!(notify.topic(), "modo/myhost/notify");
+        notify.handle(&message(
+            r#"{"title": "Backup", "urgency": "critical"}"#,
+            false,
+        ));
+        notify.handle(&message("not json", false));
+        assert_eq!(
+            recorder.notifications(),
+            [Notification {
+                title: "Backup".into(),
+                body: String::new(),
+                urgency: Urgency::Critical,
+                timeout: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn handle_ignores_retained_notifications() {
+        let recorder = Arc::new(RecordingNotifier::default());
+        let notify = Notify::with_notifier("modo/myhost", recorder.clone());
+        notify.handle(&message(r#"{"title": "Backup"}"#, true));
+        assert!(recorder.notifications().is_empty());
+    }
+}
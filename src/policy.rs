@@ -0,0 +1,270 @@
+//! Publish policies deciding which readings are worth publishing
+//!
+//! A reading is published when it changed (exactly or beyond a deadband)
+//! since the last published value, at most every `min_interval` and at least
+//! every `max_interval` seconds.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{PolicyConfig, PublishConfig},
+    sensor::Reading,
+};
+
+/// Last published value of a metric
+struct Published {
+    value: String,
+    at: Instant,
+}
+
+/// Drops readings of a sensor according to their publish policies
+pub struct PublishFilter {
+    config: Arc<PublishConfig>,
+    sensor: String,
+    policies: HashMap<String, PolicyConfig>,
+    published: HashMap<String, Published>,
+}
+
+impl PublishFilter {
+    pub fn new(config: Arc<PublishConfig>, sensor: &str) -> Self {
+        Self {
+            config,
+            sensor: sensor.to_string(),
+            policies: HashMap::new(),
+            published: HashMap::new(),
+        }
+    }
+
+    /// Readings to publish now, which are remembered as published
+    pub fn apply(&mut self, readings: Vec<Reading>) -> Vec<Reading> {
+        let now = Instant::now();
+        readings
+            .into_iter()
+            .filter(|reading| self.should_publish(reading, now))
+            .collect()
+    }
+
+    fn should_publish(&mut self, reading: &Reading, now: Instant) -> bool {
+        let policy = self
+            .policies
+            .entry(reading.metric.clone())
+            .or_insert_with(|| self.config.policy(&self.sensor, &reading.metric));
+        let last = match self.published.entry(reading.metric.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(Published {
+                    value: reading.value.clone(),
+                    at: now,
+                });
+                return true;
+            }
+            Entry::Occupied(entry) => entry.into_mut(),
+        };
+        let elapsed = now.duration_since(last.at);
+        let publish = if elapsed < secs(policy.min_interval) {
+            false
+        } else if policy
+            .max_interval
+            .is_some_and(|max| elapsed >= secs(Some(max)))
+        {
+            true
+        } else if policy.on_change.unwrap_or(false)
+            || policy.deadband.is_some()
+            || policy.deadband_percent.is_some()
+        {
+            changed(policy, &last.value, &reading.value)
+        } else {
+            true
+        };
+        if publish {
+            last.value.clone_from(&reading.value);
+            last.at = now;
+        }
+        publish
+    }
+}
+
+fn secs(seconds: Option<u64>) -> Duration {
+    Duration::from_secs(seconds.unwrap_or(0))
+}
+
+/// Whether a value differs from the last published one, beyond the deadband for numbers
+fn changed(policy: &PolicyConfig, last: &str, value: &str) -> bool {
+    match (last.parse::<f64>(), value.parse::<f64>()) {
+        (Ok(last), Ok(value)) => {
+            let delta = (value - last).abs();
+            let absolute = policy.deadband.is_none_or(|deadband| delta > deadband);
+            let percent = policy
+                .deadband_percent
+                .is_none_or(|percent| delta > last.abs() * percent / 100.0);
+            match (policy.deadband, policy.deadband_percent) {
+                (None, None) => delta > 0.0,
+                _ => absolute && percent,
+            }
+        }
+        _ => last != value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(policy: PolicyConfig) -> PublishFilter {
+        let config = PublishConfig {
+            default: policy,
+            ..Default::default()
+        };
+        PublishFilter::new(Arc::new(config), "cpu")
+    }
+
+    fn publish(filter: &mut PublishFilter, value: &str, at: Instant) -> bool {
+        filter.should_publish(&Reading::new("cpu/usage_percent", value), at)
+    }
+
+    #[test]
+    fn publishes_everything_by_default() {
+        let mut filter = filter(PolicyConfig::default());
+        let now = Instant::now();
+        assert!(publish(&mut filter, "10", now));
+        assert!(publish(&mut filter, "10", now));
+    }
+
+    #[test]
+    fn on_change() {
+        let mut filter = filter(PolicyConfig {
+            on_change: Some(true),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(publish(&mut filter, "active", now));
+        assert!(!publish(&mut filter, "active", now));
+        assert!(publish(&mut filter, "idle", now));
+    }
+
+    #[test]
+    fn deadband() {
+        let mut filter = filter(PolicyConfig {
+            deadband: Some(5.0),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(publish(&mut filter, "10", now));
+        assert!(!publish(&mut filter, "14", now));
+        assert!(!publish(&mut filter, "15", now));
+        assert!(publish(&mut filter, "15.5", now));
+        // Measured from the last published value
+        assert!(!publish(&mut filter, "11", now));
+        assert!(publish(&mut filter, "10", now));
+    }
+
+    #[test]
+    fn deadband_percent() {
+        let mut filter = filter(PolicyConfig {
+            deadband_percent: Some(10.0),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(publish(&mut filter, "200", now));
+        assert!(!publish(&mut filter, "180", now));
+        assert!(publish(&mut filter, "179", now));
+    }
+
+    #[test]
+    fn deadband_percent_from_zero() {
+        let mut filter = filter(PolicyConfig {
+            deadband_percent: Some(10.0),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(publish(&mut filter, "0", now));
+        assert!(!publish(&mut filter, "0", now));
+        assert!(publish(&mut filter, "0.1", now));
+    }
+
+    #[test]
+    fn deadband_compares_text_exactly() {
+        let mut filter = filter(PolicyConfig {
+            deadband: Some(5.0),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(publish(&mut filter, "active", now));
+        assert!(!publish(&mut filter, "active", now));
+        assert!(publish(&mut filter, "idle", now));
+    }
+
+    #[test]
+    fn min_interval() {
+        let mut filter = filter(PolicyConfig {
+            min_interval: Some(30),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        assert!(publish(&mut filter, "10", start));
+        assert!(!publish(&mut filter, "20", start + Duration::from_secs(29)));
+        assert!(publish(&mut filter, "20", start + Duration::from_secs(30)));
+        assert!(!publish(&mut filter, "30", start + Duration::from_secs(59)));
+    }
+
+    #[test]
+    fn max_interval() {
+        let mut filter = filter(PolicyConfig {
+            on_change: Some(true),
+            max_interval: Some(300),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        assert!(publish(&mut filter, "10", start));
+        assert!(!publish(
+            &mut filter,
+            "10",
+            start + Duration::from_secs(299)
+        ));
+        assert!(publish(&mut filter, "10", start + Duration::from_secs(300)));
+        // The heartbeat restarts the interval
+        assert!(!publish(
+            &mut filter,
+            "10",
+            start + Duration::from_secs(599)
+        ));
+    }
+
+    #[test]
+    fn min_interval_wins_over_change() {
+        let mut filter = filter(PolicyConfig {
+            on_change: Some(true),
+            min_interval: Some(30),
+            max_interval: Some(10),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        assert!(publish(&mut filter, "10", start));
+        assert!(!publish(&mut filter, "20", start + Duration::from_secs(20)));
+        assert!(publish(&mut filter, "10", start + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn changed_without_deadband() {
+        let policy = PolicyConfig::default();
+        assert!(!changed(&policy, "1.0", "1"));
+        assert!(changed(&policy, "1", "1.5"));
+        assert!(changed(&policy, "on", "off"));
+    }
+
+    #[test]
+    fn changed_with_both_deadbands() {
+        let policy = PolicyConfig {
+            deadband: Some(1.0),
+            deadband_percent: Some(50.0),
+            ..Default::default()
+        };
+        // Beyond the absolute deadband only
+        assert!(!changed(&policy, "10", "12"));
+        // Beyond both
+        assert!(changed(&policy, "10", "16"));
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+};
+
+use chrono::Utc;
+use rumqttc::QoS;
+
+use crate::{
+    buffer::{Buffer, Buffered},
+    config::{OutputMode, PolicyConfig, PublishConfig},
+    mqtt::{MqttClient, Properties},
+    sensor::Reading,
+    state::{STATE_TOPIC, State},
+};
+
+/// Publishes values below the `<root>/<hostname>` base topic
+#[derive(Clone)]
+pub struct Publisher {
+    /// Replaced when switching brokers
+    client: Arc<RwLock<MqttClient>>,
+    base_topic: String,
+    output_mode: OutputMode,
+    /// Message expiry by metric or sensor name
+    message_expiry: Arc<BTreeMap<String, u32>>,
+    policies: Arc<PublishConfig>,
+    state: Option<Arc<State>>,
+    buffer: Option<Arc<Mutex<Buffer>>>,
+    /// Publishers for other brokers, which get the same readings
+    mirrors: Arc<Vec<Publisher>>,
+    /// QoS of all messages, overriding the policies
+    qos: Option<u8>,
+    /// Drop messages rather than wait when the request queue is full
+    lossy: bool,
+}
+
+impl Publisher {
+    pub fn new(
+        client: MqttClient,
+        base_topic: String,
+        output_mode: OutputMode,
+        message_expiry: BTreeMap<String, u32>,
+        policies: PublishConfig,
+    ) -> Self {
+        Self {
+            client: Arc::new(RwLock::new(client)),
+            base_topic,
+            output_mode,
+            message_expiry: Arc::new(message_expiry),
+            policies: Arc::new(policies),
+            state: None,
+            buffer: None,
+            mirrors: Arc::default(),
+            qos: None,
+            lossy: false,
+        }
+    }
+
+    /// Publisher for a mirror broker, which must never hold up the primary one
+    pub fn into_mirror(mut self, qos: Option<u8>) -> Self {
+        self.qos = qos;
+        self.lossy = true;
+        self
+    }
+
+    /// Wait for room in the request queue even for a mirror, for tasks serving only this broker
+    pub fn waiting(mut self) -> Self {
+        self.lossy = false;
+        self
+    }
+
+    /// Pass readings on to the publishers of mirror brokers
+    pub fn with_mirrors(mut self, mirrors: Vec<Publisher>) -> Self {
+        self.mirrors = Arc::new(mirrors);
+        self
+    }
+
+    /// Keep readings while disconnected, until [`Publisher::replay`]
+    pub fn with_buffer(mut self, buffer: Buffer) -> Self {
+        self.buffer = Some(Arc::new(Mutex::new(buffer)));
+        self
+    }
+
+    /// Also publish a snapshot of all values on the `state` topic
+    pub fn with_state(mut self, state: State) -> Self {
+        self.state = Some(Arc::new(state));
+        self
+    }
+
+    pub fn client(&self) -> MqttClient {
+        self.client
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Publish through another client from now on
+    pub fn set_client(&self, client: MqttClient) {
+        *self.client.write().unwrap_or_else(|e| e.into_inner()) = client;
+    }
+
+    pub fn base_topic(&self) -> &str {
+        &self.base_topic
+    }
+
+    pub fn output_mode(&self) -> OutputMode {
+        self.output_mode
+    }
+
+    pub fn policies(&self) -> Arc<PublishConfig> {
+        self.policies.clone()
+    }
+
+    pub fn mirrors(&self) -> &[Publisher] {
+        &self.mirrors
+    }
+
+    /// Publish payload on specified MQTT topic, relative to the base topic
+    pub async fn publish<V: Into<Vec<u8>>>(&self, topic: &str, payload: V) {
+        self.publish_with(topic, payload, Properties::text()).await
+    }
+
+    /// Publish payload with MQTT 5 properties, relative to the base topic
+    pub async fn publish_with<V: Into<Vec<u8>>>(
+        &self,
+        topic: &str,
+        payload: V,
+        properties: Properties,
+    ) {
+        let policy = self.policies.policy(topic, topic);
+        self.send(topic, payload.into(), properties, &policy).await;
+    }
+
+    /// Publish with the QoS and retain flag of the policy
+    async fn send(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: Properties,
+        policy: &PolicyConfig,
+    ) {
+        self.send_raw(
+            topic,
+            payload,
+            properties,
+            policy.qos.unwrap_or(1),
+            policy.retain.unwrap_or(true),
+        )
+        .await;
+    }
+
+    async fn send_raw(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: Properties,
+        qos: u8,
+        retain: bool,
+    ) {
+        let qos = match self.qos.unwrap_or(qos) {
+            0 => QoS::AtMostOnce,
+            2 => QoS::ExactlyOnce,
+            _ => QoS::AtLeastOnce,
+        };
+        let client = self.client();
+        let topic_abs = [self.base_topic.as_str(), topic].join("/");
+        let result = match self.lossy {
+            true => client.try_publish(&topic_abs, qos, retain, payload, properties),
+            false => {
+                client
+                    .publish(&topic_abs, qos, retain, payload, properties)
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("mqtt_publish_{topic_abs}_error={e}");
+        }
+    }
+
+    /// Publish, or buffer while disconnected
+    async fn send_buffered(
+        &self,
+        topic: &str,
+        payload: Vec<u8>,
+        properties: Properties,
+        policy: &PolicyConfig,
+    ) {
+        if let Some(buffer) = &self.buffer {
+            let mut buffer = lock(buffer);
+            if !buffer.is_online() {
+                buffer.push(Buffered {
+                    topic: topic.to_string(),
+                    payload: String::from_utf8_lossy(&payload).into_owned(),
+                    qos: policy.qos.unwrap_or(1),
+                    retain: policy.retain.unwrap_or(true),
+                    content_type: properties.content_type,
+                    message_expiry: properties.message_expiry,
+                    timestamp: Utc::now(),
+                });
+                return;
+            }
+        }
+        self.send(topic, payload, properties, policy).await;
+    }
+
+    /// Buffer readings from now on, as the connection is lost
+    pub fn set_offline(&self) {
+        if let Some(buffer) = &self.buffer {
+            lock(buffer).set_offline();
+        }
+    }
+
+    /// Publish buffered messages in order, then publish readings right away again
+    ///
+    /// Messages carry their original time in the MQTT 5 `timestamp` user property.
+    /// Messages which expired in the meantime are dropped.
+    pub async fn replay(&self) {
+        let Some(buffer) = &self.buffer else {
+            return;
+        };
+        let mut replayed = 0;
+        loop {
+            // Readings taken meanwhile are buffered behind this batch
+            let batch = lock(buffer).take_batch();
+            if batch.is_empty() {
+                break;
+            }
+            for message in batch {
+                let age = (Utc::now() - message.timestamp).num_seconds().max(0);
+                let message_expiry = match message.message_expiry {
+                    Some(expiry) if i64::from(expiry) <= age => continue,
+                    Some(expiry) => Some(expiry - age as u32),
+                    None => None,
+                };
+                let properties = Properties {
+                    content_type: message.content_type,
+                    message_expiry,
+                    user_properties: vec![("timestamp".into(), message.timestamp.to_rfc3339())],
+                    ..Default::default()
+                };
+                self.send_raw(
+                    &message.topic,
+                    message.payload.into_bytes(),
+                    properties,
+                    message.qos,
+                    message.retain,
+                )
+                .await;
+                replayed += 1;
+            }
+        }
+        if replayed > 0 {
+            println!("Replayed {replayed} buffered messages");
+        }
+    }
+
+    /// Mark this host as disconnected, as the last will would
+    pub async fn publish_disconnected(&self) {
+        match self.output_mode {
+            OutputMode::Plain => self.publish("connected", "false").await,
+            OutputMode::Homie => self.publish("$state", crate::homie::DISCONNECTED).await,
+        }
+    }
+
+    /// Remove the retained message of a topic, relative to the base topic
+    pub async fn clear(&self, topic: &str) {
+        self.send(
+            topic,
+            Vec::new(),
+            Properties::default(),
+            &PolicyConfig {
+                qos: Some(1),
+                retain: Some(true),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    /// Publish payload, not retained, on an absolute topic
+    pub async fn respond<V: Into<Vec<u8>>>(&self, topic: &str, payload: V, properties: Properties) {
+        if let Err(e) = self
+            .client()
+            .publish(topic, QoS::AtLeastOnce, false, payload.into(), properties)
+            .await
+        {
+            eprintln!("mqtt_respond_{topic}_error={e}");
+        }
+    }
+
+    /// Publish sensor readings on the topics given by the output mode, to this and the mirror brokers
+    pub async fn publish_readings(&self, sensor: &str, readings: &[Reading]) {
+        self.publish_own_readings(sensor, readings).await;
+        for mirror in self.mirrors.iter() {
+            mirror.publish_own_readings(sensor, readings).await;
+        }
+    }
+
+    async fn publish_own_readings(&self, sensor: &str, readings: &[Reading]) {
+        for reading in readings {
+            let topic = self.output_mode.metric_path(sensor, &reading.metric);
+            let message_expiry = self
+                .message_expiry
+                .get(&reading.metric)
+                .or_else(|| self.message_expiry.get(sensor))
+                .copied();
+            self.send_buffered(
+                &topic,
+                reading.value.clone().into_bytes(),
+                Properties {
+                    message_expiry,
+                    ..Properties::text()
+                },
+                &self.policies.policy(sensor, &reading.metric),
+            )
+            .await;
+        }
+        if let Some(state) = &self.state {
+            state
+                .update(readings, |payload| async move {
+                    let policy = self.policies.policy(STATE_TOPIC, STATE_TOPIC);
+                    self.send_buffered(STATE_TOPIC, payload, Properties::json(), &policy)
+                        .await
+                })
+                .await;
+        }
+    }
+}
+
+fn lock(buffer: &Mutex<Buffer>) -> MutexGuard<'_, Buffer> {
+    buffer.lock().unwrap_or_else(|e| e.into_inner())
+}
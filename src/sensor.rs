@@ -0,0 +1,235 @@
+//! Sensors periodically read values which are published below the base topic
+
+use std::time::Duration;
+
+use tokio::task::{self, JoinHandle};
+
+use crate::{Config, Result, policy::PublishFilter, publisher::Publisher, shutdown::Shutdown};
+
+pub use self::cpu::CpuSensor;
+pub use self::disk::DiskSensor;
+pub use self::hwmon::HwmonSensor;
+pub use self::idle::IdleSensor;
+pub use self::load::LoadSensor;
+pub use self::memory::MemorySensor;
+pub use self::network::NetworkSensor;
+pub use self::power_supply::PowerSupplySensor;
+pub use self::uptime::UptimeSensor;
+
+pub mod cpu;
+pub mod disk;
+pub mod hwmon;
+mod idle;
+pub mod load;
+pub mod memory;
+pub mod network;
+pub mod power_supply;
+pub mod uptime;
+
+/// A single value read from a sensor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    /// Metric id, also the topic relative to the base topic, e.g. `idle_seconds`
+    pub metric: String,
+    pub value: String,
+}
+
+impl Reading {
+    pub fn new<M: Into<String>, V: ToString>(metric: M, value: V) -> Self {
+        Self {
+            metric: metric.into(),
+            value: value.to_string(),
+        }
+    }
+}
+
+/// Type of the values published for a metric
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datatype {
+    Integer,
+    Float,
+    Boolean,
+    String,
+    Enum(Vec<String>),
+    Datetime,
+}
+
+/// Description of a metric, used for discovery metadata
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub id: String,
+    pub name: String,
+    pub datatype: Datatype,
+    pub unit: Option<String>,
+    /// Home Assistant device class, e.g. `duration` or `temperature`
+    pub device_class: Option<&'static str>,
+    /// Only ever increases, apart from resets, e.g. bytes received since boot
+    pub counter: bool,
+}
+
+impl Metric {
+    pub fn new<I: Into<String>, N: Into<String>>(id: I, name: N, datatype: Datatype) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            datatype,
+            unit: None,
+            device_class: None,
+            counter: false,
+        }
+    }
+
+    pub fn unit<U: Into<String>>(mut self, unit: U) -> Self {
+        self.unit = Some(unit.into());
+        self
+    }
+
+    pub fn device_class(mut self, device_class: &'static str) -> Self {
+        self.device_class = Some(device_class);
+        self
+    }
+
+    pub fn counter(mut self) -> Self {
+        self.counter = true;
+        self
+    }
+}
+
+/// Source of periodic readings
+pub trait Sensor: Send {
+    /// Unique name, used for log output and discovery nodes
+    fn name(&self) -> &str;
+    /// Time between reads
+    fn interval(&self) -> Duration;
+    /// Read current values
+    fn read(&mut self) -> Result<Vec<Reading>>;
+    /// Metrics this sensor publishes, for discovery metadata
+    fn metrics(&self) -> Vec<Metric> {
+        Vec::new()
+    }
+}
+
+/// Description of the metrics of one sensor
+#[derive(Debug, Clone)]
+pub struct SensorInfo {
+    pub name: String,
+    pub metrics: Vec<Metric>,
+}
+
+/// Collection of enabled sensors
+#[derive(Default)]
+pub struct Registry {
+    sensors: Vec<Box<dyn Sensor>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create registry with all sensors enabled in configuration
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = Self::new();
+        if config.sensors.idle.enabled {
+            registry.register(IdleSensor::new(
+                Duration::from_secs(config.sensors.idle.interval),
+                config.threshold_active,
+                config.threshold_idle,
+            ));
+        }
+        let sensors = &config.sensors;
+        if sensors.cpu.enabled {
+            registry.register(CpuSensor::new(
+                Duration::from_secs(sensors.cpu.interval),
+                sensors.proc_path.clone(),
+            ));
+        }
+        if sensors.load.enabled {
+            registry.register(LoadSensor::new(
+                Duration::from_secs(sensors.load.interval),
+                sensors.proc_path.clone(),
+            ));
+        }
+        if sensors.memory.enabled {
+            registry.register(MemorySensor::new(
+                Duration::from_secs(sensors.memory.interval),
+                sensors.proc_path.clone(),
+            ));
+        }
+        if sensors.uptime.enabled {
+            registry.register(UptimeSensor::new(
+                Duration::from_secs(sensors.uptime.interval),
+                sensors.proc_path.clone(),
+            ));
+        }
+        if sensors.disk.enabled {
+            registry.register(DiskSensor::new(
+                Duration::from_secs(sensors.disk.interval),
+                &sensors.proc_path,
+                &sensors.disk,
+            ));
+        }
+        if sensors.network.enabled {
+            registry.register(NetworkSensor::new(
+                Duration::from_secs(sensors.network.interval),
+                &sensors.proc_path,
+                &sensors.sys_path,
+                &sensors.network,
+            ));
+        }
+        if sensors.power.enabled {
+            registry.register(PowerSupplySensor::new(
+                Duration::from_secs(sensors.power.interval),
+                &sensors.sys_path,
+            ));
+        }
+        if sensors.hwmon.enabled {
+            registry.register(HwmonSensor::new(
+                Duration::from_secs(sensors.hwmon.interval),
+                &sensors.sys_path,
+            ));
+        }
+        registry
+    }
+
+    pub fn register<S: Sensor + 'static>(&mut self, sensor: S) {
+        self.sensors.push(Box::new(sensor));
+    }
+
+    /// Metrics of all registered sensors
+    pub fn describe(&self) -> Vec<SensorInfo> {
+        self.sensors
+            .iter()
+            .map(|sensor| SensorInfo {
+                name: sensor.name().to_string(),
+                metrics: sensor.metrics(),
+            })
+            .collect()
+    }
+
+    /// Poll every sensor in its own task, so a slow or failing sensor doesn't stall the others
+    ///
+    /// Reads block, so they run with [`task::block_in_place`] and need the multi-threaded
+    /// runtime. The tasks finish once `shutdown` is triggered.
+    pub fn spawn(self, publisher: Publisher, shutdown: Shutdown) -> Vec<JoinHandle<()>> {
+        let mut handles = Vec::with_capacity(self.sensors.len());
+        for mut sensor in self.sensors {
+            let publisher = publisher.clone();
+            let shutdown = shutdown.clone();
+            let mut filter = PublishFilter::new(publisher.policies(), sensor.name());
+            handles.push(tokio::spawn(async move {
+                while !shutdown.sleep(sensor.interval()).await {
+                    match task::block_in_place(|| sensor.read()) {
+                        Ok(readings) => {
+                            let readings = filter.apply(readings);
+                            publisher.publish_readings(sensor.name(), &readings).await
+                        }
+                        // Print error and try again later
+                        Err(e) => eprintln!("sensor_{}_error={e}", sensor.name()),
+                    }
+                }
+            }));
+        }
+        handles
+    }
+}
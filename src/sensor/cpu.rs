@@ -0,0 +1,212 @@
+use std::{path::PathBuf, time::Duration};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result};
+
+/// CPU utilisation, total and per core, from `/proc/stat`
+///
+/// Utilisation is computed from the difference between two reads, so the first read publishes nothing.
+pub struct CpuSensor {
+    interval: Duration,
+    path: PathBuf,
+    cores: usize,
+    previous: Option<Vec<CpuTimes>>,
+}
+
+/// Jiffies spent by a CPU, as listed on a `cpu` line in `/proc/stat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub idle: u64,
+}
+
+impl CpuSensor {
+    pub fn new(interval: Duration, proc_path: PathBuf) -> Self {
+        let path = proc_path.join("stat");
+        let cores = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|stat| parse_stat(&stat).ok())
+            .map_or(0, |times| times.len().saturating_sub(1));
+        Self {
+            interval,
+            path,
+            cores,
+            previous: None,
+        }
+    }
+}
+
+impl Sensor for CpuSensor {
+    fn name(&self) -> &str {
+        "cpu"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let current = parse_stat(&std::fs::read_to_string(&self.path)?)?;
+        let Some(previous) = self.previous.replace(current.clone()) else {
+            return Ok(Vec::new());
+        };
+        let readings = current
+            .iter()
+            .zip(previous.iter())
+            .enumerate()
+            .map(|(i, (current, previous))| {
+                let metric = match i {
+                    0 => "cpu/usage_percent".to_string(),
+                    i => format!("cpu/core{}/usage_percent", i - 1),
+                };
+                Reading::new(metric, format!("{:.1}", usage_percent(previous, current)))
+            })
+            .collect();
+        Ok(readings)
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        let mut metrics =
+            vec![Metric::new("cpu/usage_percent", "CPU usage", Datatype::Float).unit("%")];
+        for core in 0..self.cores {
+            metrics.push(
+                Metric::new(
+                    format!("cpu/core{core}/usage_percent"),
+                    format!("CPU core {core} usage"),
+                    Datatype::Float,
+                )
+                .unit("%"),
+            );
+        }
+        metrics
+    }
+}
+
+/// Parse the aggregate `cpu` line followed by one `cpuN` line per core
+pub fn parse_stat(stat: &str) -> Result<Vec<CpuTimes>> {
+    let times = stat
+        .lines()
+        .filter(|line| line.starts_with("cpu"))
+        .map(|line| {
+            let fields = line
+                .split_whitespace()
+                .skip(1)
+                .take(8)
+                .map(|field| field.parse::<u64>())
+                .collect::<core::result::Result<Vec<_>, _>>()
+                .map_err(|e| Error::Sensor(format!("invalid /proc/stat line {line:?}: {e}")))?;
+            // user nice system idle iowait irq softirq steal
+            let [user, nice, system, idle, iowait, irq, softirq, steal] = fields[..] else {
+                return Err(Error::Sensor(format!("short /proc/stat line {line:?}")));
+            };
+            Ok(CpuTimes {
+                busy: user + nice + system + irq + softirq + steal,
+                idle: idle + iowait,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if times.is_empty() {
+        return Err(Error::Sensor("no cpu lines in /proc/stat".into()));
+    }
+    Ok(times)
+}
+
+/// Percentage of time spent busy between two reads
+pub fn usage_percent(previous: &CpuTimes, current: &CpuTimes) -> f64 {
+    let busy = current.busy.saturating_sub(previous.busy);
+    let idle = current.idle.saturating_sub(previous.idle);
+    match busy + idle {
+        0 => 0.0,
+        total => busy as f64 * 100.0 / total as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT: &str = "\
+cpu  10132153 290696 3084719 46828483 16683 0 25195 0 175628 0
+cpu0 1393280 32966 572056 13343292 6130 0 17875 0 23933 0
+cpu1 1335100 29539 412163 13387164 3512 0 2706 0 26512 0
+intr 1462898 22 0 0 0 0 0 0 0 1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+ctxt 2397823
+btime 1711378311
+processes 23187
+";
+
+    #[test]
+    fn parses_aggregate_and_cores() {
+        let times = parse_stat(STAT).unwrap();
+        assert_eq!(times.len(), 3);
+        assert_eq!(
+            times[0],
+            CpuTimes {
+                busy: 10132153 + 290696 + 3084719 + 25195,
+                idle: 46828483 + 16683,
+            }
+        );
+        assert_eq!(
+            times[2],
+            CpuTimes {
+                busy: 1335100 + 29539 + 412163 + 2706,
+                idle: 13387164 + 3512,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_short_cpu_line() {
+        let stat = "cpu  10132153 290696 3084719 46828483\n";
+        assert!(matches!(parse_stat(stat), Err(Error::Sensor(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_cpu_line() {
+        let stat = "cpu  10132153 290696 x 46828483 16683 0 25195 0\n";
+        assert!(matches!(parse_stat(stat), Err(Error::Sensor(_))));
+    }
+
+    #[test]
+    fn rejects_missing_cpu_lines() {
+        assert!(matches!(
+            parse_stat("ctxt 2397823\n"),
+            Err(Error::Sensor(_))
+        ));
+    }
+
+    #[test]
+    fn usage_between_reads() {
+        let previous = CpuTimes {
+            busy: 1000,
+            idle: 3000,
+        };
+        let current = CpuTimes {
+            busy: 1250,
+            idle: 3750,
+        };
+        assert_eq!(usage_percent(&previous, &current), 25.0);
+    }
+
+    #[test]
+    fn usage_without_elapsed_time() {
+        let times = CpuTimes {
+            busy: 1000,
+            idle: 3000,
+        };
+        assert_eq!(usage_percent(&times, &times), 0.0);
+    }
+
+    #[test]
+    fn usage_after_counter_reset() {
+        let previous = CpuTimes {
+            busy: 1000,
+            idle: 3000,
+        };
+        let current = CpuTimes {
+            busy: 100,
+            idle: 300,
+        };
+        assert_eq!(usage_percent(&previous, &current), 0.0);
+    }
+}
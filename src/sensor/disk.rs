@@ -0,0 +1,300 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result, config::DiskConfig};
+
+/// Filesystem types which aren't backed by a block device, but still hold real data
+const NETWORK_FS_TYPES: [&str; 7] = ["nfs", "nfs4", "cifs", "smb3", "zfs", "fuseblk", "9p"];
+
+/// Read-only image filesystems, e.g. snap packages, which are always full
+const IMAGE_FS_TYPES: [&str; 5] = ["squashfs", "iso9660", "udf", "erofs", "cramfs"];
+
+/// Total, used and free space per mount point
+pub struct DiskSensor {
+    interval: Duration,
+    mounts_path: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    mount_points: Vec<String>,
+}
+
+/// Space on a filesystem, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total: u64,
+    /// Space available to unprivileged users
+    pub free: u64,
+    pub used: u64,
+}
+
+impl DiskSensor {
+    pub fn new(interval: Duration, proc_path: &Path, config: &DiskConfig) -> Self {
+        let mut sensor = Self {
+            interval,
+            mounts_path: proc_path.join("mounts"),
+            include: config.include.clone(),
+            exclude: config.exclude.clone(),
+            mount_points: Vec::new(),
+        };
+        sensor.mount_points = sensor.mount_points().unwrap_or_default();
+        sensor
+    }
+
+    /// Mount points to report, either the configured ones or all real filesystems
+    fn mount_points(&self) -> Result<Vec<String>> {
+        let mount_points = if self.include.is_empty() {
+            parse_mounts(&std::fs::read_to_string(&self.mounts_path)?)
+        } else {
+            self.include.clone()
+        };
+        Ok(mount_points
+            .into_iter()
+            .filter(|mount_point| !self.exclude.contains(mount_point))
+            .collect())
+    }
+}
+
+impl Sensor for DiskSensor {
+    fn name(&self) -> &str {
+        "disk"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        // Pick up filesystems mounted since the previous read
+        if self.include.is_empty() {
+            self.mount_points = self.mount_points()?;
+        }
+        let mut readings = Vec::new();
+        for mount_point in &self.mount_points {
+            let usage = match disk_usage(Path::new(mount_point)) {
+                Ok(usage) => usage,
+                Err(e) => {
+                    eprintln!("sensor_disk_{mount_point}_error={e}");
+                    continue;
+                }
+            };
+            let id = mount_id(mount_point);
+            readings.push(Reading::new(format!("disk/{id}/total_bytes"), usage.total));
+            readings.push(Reading::new(format!("disk/{id}/used_bytes"), usage.used));
+            readings.push(Reading::new(format!("disk/{id}/free_bytes"), usage.free));
+            let used_percent = match usage.used + usage.free {
+                0 => 0.0,
+                size => usage.used as f64 * 100.0 / size as f64,
+            };
+            readings.push(Reading::new(
+                format!("disk/{id}/used_percent"),
+                format!("{used_percent:.1}"),
+            ));
+        }
+        Ok(readings)
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        let mut metrics = Vec::new();
+        for mount_point in &self.mount_points {
+            let id = mount_id(mount_point);
+            for (metric, name) in [
+                ("total_bytes", "total"),
+                ("used_bytes", "used"),
+                ("free_bytes", "free"),
+            ] {
+                metrics.push(
+                    Metric::new(
+                        format!("disk/{id}/{metric}"),
+                        format!("Disk {mount_point} {name}"),
+                        Datatype::Integer,
+                    )
+                    .unit("B")
+                    .device_class("data_size"),
+                );
+            }
+            metrics.push(
+                Metric::new(
+                    format!("disk/{id}/used_percent"),
+                    format!("Disk {mount_point} usage"),
+                    Datatype::Float,
+                )
+                .unit("%"),
+            );
+        }
+        metrics
+    }
+}
+
+/// Mount points of real filesystems listed in `/proc/mounts`
+pub fn parse_mounts(mounts: &str) -> Vec<String> {
+    let mut mount_points = Vec::new();
+    for line in mounts.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(source), Some(mount_point), Some(fs_type)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if !source.starts_with('/') && !NETWORK_FS_TYPES.contains(&fs_type)
+            || IMAGE_FS_TYPES.contains(&fs_type)
+        {
+            continue;
+        }
+        let mount_point = unescape_mount_point(mount_point);
+        if !mount_points.contains(&mount_point) {
+            mount_points.push(mount_point);
+        }
+    }
+    mount_points
+}
+
+/// `/proc/mounts` escapes space, tab, newline and backslash as octal, e.g. `\040`
+fn unescape_mount_point(mount_point: &str) -> String {
+    let mut unescaped = String::with_capacity(mount_point.len());
+    let mut rest = mount_point;
+    while let Some(pos) = rest.find('\\') {
+        unescaped.push_str(&rest[..pos]);
+        let code = rest
+            .get(pos + 1..pos + 4)
+            .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+        match code {
+            Some(code) => {
+                unescaped.push(code as char);
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Topic level for a mount point, `/` becomes `_` and `/mnt/data` becomes `mnt_data`
+///
+/// Other bytes than letters, digits and `.` are escaped as `-` and two hex digits,
+/// e.g. `/mnt_data` becomes `mnt-5fdata`, so different mount points never share an id.
+pub fn mount_id(mount_point: &str) -> String {
+    let path = mount_point.strip_prefix('/').unwrap_or(mount_point);
+    if path.is_empty() {
+        return "_".into();
+    }
+    let mut id = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' => id.push(byte as char),
+            b'/' => id.push('_'),
+            byte => id.push_str(&format!("-{byte:02x}")),
+        }
+    }
+    id
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // statvfs field types differ between platforms
+fn disk_usage(mount_point: &Path) -> Result<DiskUsage> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(mount_point.as_os_str().as_bytes())
+        .map_err(|e| Error::Sensor(format!("invalid mount point {mount_point:?}: {e}")))?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: path is NUL terminated and stat points to writable memory of the correct size
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // SAFETY: statvfs succeeded, so stat has been initialized
+    let stat = unsafe { stat.assume_init() };
+    let fragment_size = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * fragment_size;
+    let free = stat.f_bavail as u64 * fragment_size;
+    let used = total.saturating_sub(stat.f_bfree as u64 * fragment_size);
+    Ok(DiskUsage { total, free, used })
+}
+
+#[cfg(not(unix))]
+fn disk_usage(mount_point: &Path) -> Result<DiskUsage> {
+    Err(Error::Sensor(format!(
+        "disk usage of {mount_point:?} is not supported on this platform"
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mounts_keeps_real_filesystems() {
+        let mounts = "\
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0
+tmpfs /run tmpfs rw,nosuid,nodev,size=3260116k,mode=755 0 0
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+/dev/nvme0n1p1 /boot/efi vfat rw,relatime 0 0
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+server:/export /mnt/nfs nfs4 rw,relatime 0 0
+//server/share /mnt/smb cifs rw,relatime 0 0
+tank/home /home zfs rw,xattr,noacl 0 0
+/dev/loop3 /snap/core22/1380 squashfs ro,nodev,relatime 0 0
+/dev/sr0 /media/cdrom iso9660 ro,nosuid,nodev,relatime 0 0
+/dev/sdb1 /media/USB\\040Stick vfat rw,relatime 0 0
+incomplete line
+";
+        assert_eq!(
+            parse_mounts(mounts),
+            [
+                "/",
+                "/boot/efi",
+                "/mnt/nfs",
+                "/mnt/smb",
+                "/home",
+                "/media/USB Stick"
+            ]
+        );
+    }
+
+    #[test]
+    fn unescape_mount_point_decodes_octal() {
+        assert_eq!(unescape_mount_point(r"/mnt/my\040disk"), "/mnt/my disk");
+        assert_eq!(unescape_mount_point(r"/tab\011and\134"), "/tab\tand\\");
+        assert_eq!(unescape_mount_point("/plain"), "/plain");
+        // Not followed by three octal digits, kept as is
+        assert_eq!(unescape_mount_point(r"/odd\09"), r"/odd\09");
+        assert_eq!(unescape_mount_point(r"/end\"), r"/end\");
+    }
+
+    #[test]
+    fn mount_id_is_readable() {
+        assert_eq!(mount_id("/"), "_");
+        assert_eq!(mount_id("/root"), "root");
+        assert_eq!(mount_id("/mnt/data"), "mnt_data");
+        assert_eq!(mount_id("/mnt_data"), "mnt-5fdata");
+        assert_eq!(mount_id("/mnt data"), "mnt-20data");
+        assert_eq!(mount_id("/media/v1.2"), "media_v1.2");
+    }
+
+    #[test]
+    fn mount_id_is_unique() {
+        let mount_points = [
+            "/",
+            "/root",
+            "/_",
+            "/mnt/data",
+            "/mnt_data",
+            "/mnt data",
+            "/mnt-data",
+            "/mnt-5fdata",
+            "/mnt/#",
+            "/mnt/+",
+        ];
+        let mut ids: Vec<_> = mount_points.iter().map(|m| mount_id(m)).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), mount_points.len());
+        assert!(ids.iter().all(|id| !id.contains(['/', '+', '#'])));
+    }
+}
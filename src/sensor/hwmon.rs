@@ -0,0 +1,334 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result};
+
+/// Temperatures and fan speeds from `/sys/class/hwmon` and `/sys/class/thermal`
+pub struct HwmonSensor {
+    interval: Duration,
+    sys_path: PathBuf,
+    channels: Vec<Channel>,
+}
+
+/// What a channel measures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelKind {
+    /// Millidegrees Celsius
+    Temperature,
+    /// Revolutions per minute
+    Fan,
+}
+
+/// A single temperature or fan input file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    /// Metric id, e.g. `hwmon/coretemp/package_id_0_celsius`
+    pub id: String,
+    /// Human readable name, e.g. `coretemp Package id 0`
+    pub name: String,
+    pub kind: ChannelKind,
+    pub input: PathBuf,
+}
+
+impl Channel {
+    /// Current value, formatted for publishing
+    fn read(&self) -> Result<String> {
+        let value = fs::read_to_string(&self.input)?;
+        let value = value
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| Error::Sensor(format!("invalid value in {:?}: {e}", self.input)))?;
+        Ok(match self.kind {
+            ChannelKind::Temperature => format!("{:.1}", value as f64 / 1000.0),
+            ChannelKind::Fan => value.to_string(),
+        })
+    }
+}
+
+impl HwmonSensor {
+    pub fn new(interval: Duration, sys_path: &Path) -> Self {
+        let sys_path = sys_path.to_path_buf();
+        let channels = discover(&sys_path);
+        Self {
+            interval,
+            sys_path,
+            channels,
+        }
+    }
+}
+
+impl Sensor for HwmonSensor {
+    fn name(&self) -> &str {
+        "hwmon"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        // Drivers may be loaded after startup
+        if self.channels.is_empty() {
+            self.channels = discover(&self.sys_path);
+        }
+        let mut readings = Vec::new();
+        for channel in &self.channels {
+            // A sensor which can't be read, e.g. a disconnected fan, doesn't hold up the others
+            let value = match channel.read() {
+                Ok(value) => value,
+                Err(e) => {
+                    eprintln!("sensor_{}_error={e}", channel.id);
+                    continue;
+                }
+            };
+            readings.push(Reading::new(channel.id.as_str(), value));
+        }
+        Ok(readings)
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        self.channels
+            .iter()
+            .map(|channel| match channel.kind {
+                ChannelKind::Temperature => {
+                    Metric::new(channel.id.as_str(), channel.name.as_str(), Datatype::Float)
+                        .unit("°C")
+                        .device_class("temperature")
+                }
+                ChannelKind::Fan => Metric::new(
+                    channel.id.as_str(),
+                    channel.name.as_str(),
+                    Datatype::Integer,
+                )
+                .unit("RPM"),
+            })
+            .collect()
+    }
+}
+
+/// Find all temperature and fan inputs
+pub fn discover(sys_path: &Path) -> Vec<Channel> {
+    let mut channels = Vec::new();
+    for (dir, chip) in class_devices(&sys_path.join("class").join("hwmon"), "name") {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut inputs = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let channel = file_name.strip_suffix("_input")?.to_string();
+                let kind = match channel {
+                    ref c if c.starts_with("temp") => ChannelKind::Temperature,
+                    ref c if c.starts_with("fan") => ChannelKind::Fan,
+                    _ => return None,
+                };
+                Some((channel, kind))
+            })
+            .collect::<Vec<_>>();
+        inputs.sort_by(|a, b| a.0.cmp(&b.0));
+        for (channel, kind) in inputs {
+            let label = read_attribute(&dir.join(format!("{channel}_label")))
+                .unwrap_or_else(|| channel.clone());
+            let suffix = match kind {
+                ChannelKind::Temperature => "celsius",
+                ChannelKind::Fan => "rpm",
+            };
+            channels.push(Channel {
+                id: format!("hwmon/{}/{}_{suffix}", metric_id(&chip), metric_id(&label)),
+                name: format!("{chip} {label}"),
+                kind,
+                input: dir.join(format!("{channel}_input")),
+            });
+        }
+    }
+    for (dir, zone) in class_devices(&sys_path.join("class").join("thermal"), "type") {
+        let input = dir.join("temp");
+        if !input.is_file() {
+            continue;
+        }
+        channels.push(Channel {
+            id: format!("hwmon/thermal/{}_celsius", metric_id(&zone)),
+            name: format!("Thermal zone {zone}"),
+            kind: ChannelKind::Temperature,
+            input,
+        });
+    }
+    channels
+}
+
+/// Devices of a sysfs class with their name read from `attribute`
+///
+/// Names are made unique by appending the device directory name on collision.
+fn class_devices(class_path: &Path, attribute: &str) -> Vec<(PathBuf, String)> {
+    let Ok(entries) = fs::read_dir(class_path) else {
+        return Vec::new();
+    };
+    let mut devices = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let dir = entry.path();
+            let device = entry.file_name().to_string_lossy().into_owned();
+            let name = read_attribute(&dir.join(attribute)).unwrap_or_else(|| device.clone());
+            (dir, device, name)
+        })
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| a.1.cmp(&b.1));
+    let names = devices
+        .iter()
+        .map(|(_, _, name)| name.clone())
+        .collect::<Vec<_>>();
+    devices
+        .into_iter()
+        .map(
+            |(dir, device, name)| match names.iter().filter(|other| **other == name).count() {
+                1 => (dir, name),
+                _ => (dir, format!("{name} {device}")),
+            },
+        )
+        .collect()
+}
+
+fn read_attribute(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Lowercase letters, digits and underscores only
+fn metric_id(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fake sysfs tree unique to the test, removed when dropped
+    struct SysFs(PathBuf);
+
+    impl SysFs {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("modo-test-{}-{name}", std::process::id()));
+            for (file, contents) in files {
+                let file = path.join(file);
+                fs::create_dir_all(file.parent().unwrap()).unwrap();
+                fs::write(file, format!("{contents}\n")).unwrap();
+            }
+            Self(path)
+        }
+    }
+
+    impl Drop for SysFs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sys_fs(name: &str) -> SysFs {
+        SysFs::new(
+            name,
+            &[
+                ("class/hwmon/hwmon0/name", "nvme"),
+                ("class/hwmon/hwmon0/temp1_input", "35800"),
+                ("class/hwmon/hwmon0/temp1_label", "Composite"),
+                ("class/hwmon/hwmon0/temp2_input", "40000"),
+                ("class/hwmon/hwmon1/name", "nvme"),
+                ("class/hwmon/hwmon1/temp1_input", "30000"),
+                ("class/hwmon/hwmon2/name", "thinkpad"),
+                ("class/hwmon/hwmon2/fan1_input", "2100"),
+                ("class/hwmon/hwmon2/pwm1", "128"),
+                ("class/thermal/thermal_zone0/type", "x86_pkg_temp"),
+                ("class/thermal/thermal_zone0/temp", "45000"),
+                ("class/thermal/cooling_device0/type", "Processor"),
+            ],
+        )
+    }
+
+    #[test]
+    fn class_devices_disambiguates_shared_names() {
+        let sys_fs = sys_fs("hwmon-class-devices");
+        let devices = class_devices(&sys_fs.0.join("class").join("hwmon"), "name");
+        let names = devices
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["nvme hwmon0", "nvme hwmon1", "thinkpad"]);
+        assert_eq!(devices[2].0, sys_fs.0.join("class/hwmon/hwmon2"));
+    }
+
+    #[test]
+    fn class_devices_of_missing_class() {
+        let sys_fs = sys_fs("hwmon-missing-class");
+        assert!(class_devices(&sys_fs.0.join("class").join("missing"), "name").is_empty());
+    }
+
+    #[test]
+    fn discover_finds_labelled_and_unlabelled_inputs() {
+        let sys_fs = sys_fs("hwmon-discover");
+        let channels = discover(&sys_fs.0);
+        let summary = channels
+            .iter()
+            .map(|c| (c.id.as_str(), c.name.as_str(), c.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "hwmon/nvme_hwmon0/composite_celsius",
+                    "nvme hwmon0 Composite",
+                    ChannelKind::Temperature
+                ),
+                (
+                    "hwmon/nvme_hwmon0/temp2_celsius",
+                    "nvme hwmon0 temp2",
+                    ChannelKind::Temperature
+                ),
+                (
+                    "hwmon/nvme_hwmon1/temp1_celsius",
+                    "nvme hwmon1 temp1",
+                    ChannelKind::Temperature
+                ),
+                ("hwmon/thinkpad/fan1_rpm", "thinkpad fan1", ChannelKind::Fan),
+                (
+                    "hwmon/thermal/x86_pkg_temp_celsius",
+                    "Thermal zone x86_pkg_temp",
+                    ChannelKind::Temperature
+                ),
+            ]
+        );
+        assert_eq!(
+            channels[0].input,
+            sys_fs.0.join("class/hwmon/hwmon0/temp1_input")
+        );
+    }
+
+    #[test]
+    fn read_formats_and_skips_unreadable_channels() {
+        let sys_fs = sys_fs("hwmon-read");
+        let mut sensor = HwmonSensor::new(Duration::from_secs(1), &sys_fs.0);
+        fs::write(sys_fs.0.join("class/hwmon/hwmon1/temp1_input"), "invalid\n").unwrap();
+        let readings = sensor.read().unwrap();
+        assert_eq!(
+            readings,
+            [
+                Reading::new("hwmon/nvme_hwmon0/composite_celsius", "35.8"),
+                Reading::new("hwmon/nvme_hwmon0/temp2_celsius", "40.0"),
+                Reading::new("hwmon/thinkpad/fan1_rpm", "2100"),
+                Reading::new("hwmon/thermal/x86_pkg_temp_celsius", "45.0"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use chrono::{SubsecRound, Utc};
+use user_idle::UserIdle;
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::Result;
+
+/// Time since last user input
+pub struct IdleSensor {
+    interval: Duration,
+    threshold_active: u64,
+    threshold_idle: u64,
+    previous_published_idle_sec: u64,
+}
+
+impl IdleSensor {
+    pub fn new(interval: Duration, threshold_active: u64, threshold_idle: u64) -> Self {
+        Self {
+            interval,
+            threshold_active,
+            threshold_idle,
+            previous_published_idle_sec: u64::MAX - 1,
+        }
+    }
+}
+
+impl Sensor for IdleSensor {
+    fn name(&self) -> &str {
+        "idle"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let idle_sec = UserIdle::get_time()?.as_seconds();
+        let idle_status = match idle_sec {
+            i if i < self.threshold_active => "active",
+            i if i < self.threshold_idle => "idle",
+            _ => "away",
+        };
+        let mut readings = vec![
+            Reading::new("idle_seconds", idle_sec),
+            Reading::new("idle_status", idle_status),
+        ];
+        // If idle_sec is increasing, don't publish last active timestamp
+        if idle_sec <= self.previous_published_idle_sec {
+            let now = Utc::now().trunc_subsecs(0);
+            let idle_ts = now - Duration::from_secs(idle_sec);
+            readings.push(Reading::new("last_active_timestamp", idle_ts.to_rfc3339()));
+            self.previous_published_idle_sec = idle_sec;
+        }
+        Ok(readings)
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        vec![
+            Metric::new("idle_seconds", "Idle time", Datatype::Integer)
+                .unit("s")
+                .device_class("duration"),
+            Metric::new(
+                "idle_status",
+                "Idle status",
+                Datatype::Enum(vec!["active".into(), "idle".into(), "away".into()]),
+            ),
+            Metric::new("last_active_timestamp", "Last active", Datatype::Datetime)
+                .device_class("timestamp"),
+        ]
+    }
+}
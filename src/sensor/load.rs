@@ -0,0 +1,82 @@
+use std::{path::PathBuf, time::Duration};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result};
+
+/// Load averages from `/proc/loadavg`
+pub struct LoadSensor {
+    interval: Duration,
+    path: PathBuf,
+}
+
+impl LoadSensor {
+    pub fn new(interval: Duration, proc_path: PathBuf) -> Self {
+        Self {
+            interval,
+            path: proc_path.join("loadavg"),
+        }
+    }
+}
+
+impl Sensor for LoadSensor {
+    fn name(&self) -> &str {
+        "load"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let [load_1m, load_5m, load_15m] = parse_loadavg(&std::fs::read_to_string(&self.path)?)?;
+        Ok(vec![
+            Reading::new("load/1m", load_1m),
+            Reading::new("load/5m", load_5m),
+            Reading::new("load/15m", load_15m),
+        ])
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        vec![
+            Metric::new("load/1m", "Load average (1 min)", Datatype::Float),
+            Metric::new("load/5m", "Load average (5 min)", Datatype::Float),
+            Metric::new("load/15m", "Load average (15 min)", Datatype::Float),
+        ]
+    }
+}
+
+/// Parse the 1, 5 and 15 minute load averages
+pub fn parse_loadavg(loadavg: &str) -> Result<[f64; 3]> {
+    let invalid = || Error::Sensor(format!("invalid /proc/loadavg {loadavg:?}"));
+    let mut fields = loadavg.split_whitespace().map(|field| field.parse::<f64>());
+    let mut next = || fields.next().and_then(|f| f.ok()).ok_or_else(invalid);
+    Ok([next()?, next()?, next()?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_load_averages() {
+        let load = parse_loadavg("0.52 0.58 0.59 1/467 12345\n").unwrap();
+        assert_eq!(load, [0.52, 0.58, 0.59]);
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        assert!(matches!(
+            parse_loadavg("0.52 0.58\n"),
+            Err(Error::Sensor(_))
+        ));
+        assert!(matches!(parse_loadavg(""), Err(Error::Sensor(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        assert!(matches!(
+            parse_loadavg("0.52 high 0.59 1/467 12345\n"),
+            Err(Error::Sensor(_))
+        ));
+    }
+}
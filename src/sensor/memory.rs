@@ -0,0 +1,170 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result};
+
+/// Memory and swap usage from `/proc/meminfo`
+pub struct MemorySensor {
+    interval: Duration,
+    path: PathBuf,
+}
+
+/// Memory figures in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemInfo {
+    pub total: u64,
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+impl MemInfo {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+}
+
+impl MemorySensor {
+    pub fn new(interval: Duration, proc_path: PathBuf) -> Self {
+        Self {
+            interval,
+            path: proc_path.join("meminfo"),
+        }
+    }
+}
+
+impl Sensor for MemorySensor {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let info = parse_meminfo(&std::fs::read_to_string(&self.path)?)?;
+        Ok(vec![
+            Reading::new("memory/total_bytes", info.total),
+            Reading::new("memory/available_bytes", info.available),
+            Reading::new("memory/used_bytes", info.used()),
+            Reading::new(
+                "memory/used_percent",
+                format!("{:.1}", percent(info.used(), info.total)),
+            ),
+            Reading::new("memory/swap_total_bytes", info.swap_total),
+            Reading::new("memory/swap_used_bytes", info.swap_used()),
+            Reading::new(
+                "memory/swap_used_percent",
+                format!("{:.1}", percent(info.swap_used(), info.swap_total)),
+            ),
+        ])
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        let bytes = |id, name| {
+            Metric::new(id, name, Datatype::Integer)
+                .unit("B")
+                .device_class("data_size")
+        };
+        let percent = |id, name| Metric::new(id, name, Datatype::Float).unit("%");
+        vec![
+            bytes("memory/total_bytes", "Memory total"),
+            bytes("memory/available_bytes", "Memory available"),
+            bytes("memory/used_bytes", "Memory used"),
+            percent("memory/used_percent", "Memory usage"),
+            bytes("memory/swap_total_bytes", "Swap total"),
+            bytes("memory/swap_used_bytes", "Swap used"),
+            percent("memory/swap_used_percent", "Swap usage"),
+        ]
+    }
+}
+
+/// Parse `/proc/meminfo`, where values are given in kB
+pub fn parse_meminfo(meminfo: &str) -> Result<MemInfo> {
+    let values = meminfo
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let kb = value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()?;
+            Some((key, kb * 1024))
+        })
+        .collect::<HashMap<_, _>>();
+    let get = |key| {
+        values
+            .get(key)
+            .copied()
+            .ok_or_else(|| Error::Sensor(format!("{key} missing from /proc/meminfo")))
+    };
+    Ok(MemInfo {
+        total: get("MemTotal")?,
+        available: get("MemAvailable")?,
+        swap_total: get("SwapTotal")?,
+        swap_free: get("SwapFree")?,
+    })
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => part as f64 * 100.0 / total as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "\
+MemTotal:       16314304 kB
+MemFree:         1048576 kB
+MemAvailable:    8157152 kB
+Buffers:          524288 kB
+Cached:          6291456 kB
+SwapCached:            0 kB
+SwapTotal:       2097148 kB
+SwapFree:        1572860 kB
+HugePages_Total:       0
+Hugepagesize:       2048 kB
+";
+
+    #[test]
+    fn parses_meminfo() {
+        let info = parse_meminfo(MEMINFO).unwrap();
+        assert_eq!(
+            info,
+            MemInfo {
+                total: 16314304 * 1024,
+                available: 8157152 * 1024,
+                swap_total: 2097148 * 1024,
+                swap_free: 1572860 * 1024,
+            }
+        );
+        assert_eq!(info.used(), (16314304 - 8157152) * 1024);
+        assert_eq!(info.swap_used(), (2097148 - 1572860) * 1024);
+    }
+
+    #[test]
+    fn rejects_missing_mem_available() {
+        let meminfo = MEMINFO.replace("MemAvailable:    8157152 kB\n", "");
+        let Err(Error::Sensor(e)) = parse_meminfo(&meminfo) else {
+            panic!("MemAvailable should be required");
+        };
+        assert!(e.contains("MemAvailable"), "{e}");
+    }
+
+    #[test]
+    fn percent_of_nothing() {
+        assert_eq!(percent(0, 0), 0.0);
+        assert_eq!(percent(1, 4), 25.0);
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result, config::NetworkConfig};
+
+/// Per-interface throughput, link state and addresses
+///
+/// Counters come from `/proc/net/dev`, link state from `/sys/class/net/<interface>/operstate`.
+/// Rates are computed between two reads, so the first read publishes only counters.
+pub struct NetworkSensor {
+    interval: Duration,
+    dev_path: PathBuf,
+    class_path: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    interfaces: Vec<String>,
+    previous: Option<(Instant, HashMap<String, Counters>)>,
+}
+
+/// Byte counters of one interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+impl NetworkSensor {
+    pub fn new(
+        interval: Duration,
+        proc_path: &Path,
+        sys_path: &Path,
+        config: &NetworkConfig,
+    ) -> Self {
+        let mut sensor = Self {
+            interval,
+            dev_path: proc_path.join("net").join("dev"),
+            class_path: sys_path.join("class").join("net"),
+            include: config.include.clone(),
+            exclude: config.exclude.clone(),
+            interfaces: Vec::new(),
+            previous: None,
+        };
+        if let Ok(counters) = sensor.counters() {
+            sensor.interfaces = counters.into_iter().map(|(name, _)| name).collect();
+        }
+        sensor
+    }
+
+    /// Counters of all included interfaces, in the order listed by the kernel
+    fn counters(&self) -> Result<Vec<(String, Counters)>> {
+        let counters = parse_net_dev(&std::fs::read_to_string(&self.dev_path)?)?;
+        Ok(counters
+            .into_iter()
+            .filter(|(name, _)| self.include.is_empty() || self.include.contains(name))
+            .filter(|(name, _)| !self.exclude.contains(name))
+            .collect())
+    }
+
+    /// Whether the link is up, `None` if unknown
+    fn link_up(&self, interface: &str) -> Option<bool> {
+        let operstate = std::fs::read_to_string(self.class_path.join(interface).join("operstate"));
+        match operstate.ok()?.trim() {
+            "up" => Some(true),
+            "unknown" => None,
+            _ => Some(false),
+        }
+    }
+}
+
+impl Sensor for NetworkSensor {
+    fn name(&self) -> &str {
+        "network"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let now = Instant::now();
+        let counters = self.counters()?;
+        let addresses = interface_addresses().unwrap_or_else(|e| {
+            eprintln!("sensor_network_addresses_error={e}");
+            HashMap::new()
+        });
+        let mut readings = Vec::new();
+        for (interface, current) in &counters {
+            readings.push(Reading::new(
+                format!("network/{interface}/rx_bytes"),
+                current.rx_bytes,
+            ));
+            readings.push(Reading::new(
+                format!("network/{interface}/tx_bytes"),
+                current.tx_bytes,
+            ));
+            if let Some((previous_at, previous)) = &self.previous
+                && let Some(previous) = previous.get(interface)
+            {
+                let elapsed = now.duration_since(*previous_at);
+                let rx_rate = rate(current.rx_bytes, previous.rx_bytes, elapsed);
+                let tx_rate = rate(current.tx_bytes, previous.tx_bytes, elapsed);
+                readings.push(Reading::new(
+                    format!("network/{interface}/rx_bytes_per_second"),
+                    format!("{rx_rate:.0}"),
+                ));
+                readings.push(Reading::new(
+                    format!("network/{interface}/tx_bytes_per_second"),
+                    format!("{tx_rate:.0}"),
+                ));
+            }
+            if let Some(up) = self.link_up(interface) {
+                readings.push(Reading::new(format!("network/{interface}/link"), up));
+            }
+            let interface_addresses = addresses.get(interface).map(|a| a.join(","));
+            readings.push(Reading::new(
+                format!("network/{interface}/addresses"),
+                interface_addresses.unwrap_or_default(),
+            ));
+        }
+        self.interfaces = counters.iter().map(|(name, _)| name.clone()).collect();
+        self.previous = Some((now, counters.into_iter().collect()));
+        Ok(readings)
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        let mut metrics = Vec::new();
+        for interface in &self.interfaces {
+            let id = |metric| format!("network/{interface}/{metric}");
+            metrics.extend([
+                Metric::new(
+                    id("rx_bytes"),
+                    format!("{interface} received"),
+                    Datatype::Integer,
+                )
+                .unit("B")
+                .device_class("data_size")
+                .counter(),
+                Metric::new(
+                    id("tx_bytes"),
+                    format!("{interface} sent"),
+                    Datatype::Integer,
+                )
+                .unit("B")
+                .device_class("data_size")
+                .counter(),
+                Metric::new(
+                    id("rx_bytes_per_second"),
+                    format!("{interface} receive rate"),
+                    Datatype::Integer,
+                )
+                .unit("B/s")
+                .device_class("data_rate"),
+                Metric::new(
+                    id("tx_bytes_per_second"),
+                    format!("{interface} send rate"),
+                    Datatype::Integer,
+                )
+                .unit("B/s")
+                .device_class("data_rate"),
+                Metric::new(id("link"), format!("{interface} link"), Datatype::Boolean)
+                    .device_class("connectivity"),
+                Metric::new(
+                    id("addresses"),
+                    format!("{interface} addresses"),
+                    Datatype::String,
+                ),
+            ]);
+        }
+        metrics
+    }
+}
+
+/// Parse received and transmitted bytes per interface
+pub fn parse_net_dev(net_dev: &str) -> Result<Vec<(String, Counters)>> {
+    // The first two lines are headers
+    net_dev
+        .lines()
+        .skip(2)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || Error::Sensor(format!("invalid /proc/net/dev line {line:?}"));
+            let (name, fields) = line.split_once(':').ok_or_else(invalid)?;
+            let fields = fields
+                .split_whitespace()
+                .map(|field| field.parse::<u64>())
+                .collect::<core::result::Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+            // 8 receive columns followed by 8 transmit columns
+            let (Some(&rx_bytes), Some(&tx_bytes)) = (fields.first(), fields.get(8)) else {
+                return Err(invalid());
+            };
+            Ok((name.trim().to_string(), Counters { rx_bytes, tx_bytes }))
+        })
+        .collect()
+}
+
+/// Bytes per second between two counter values
+fn rate(current: u64, previous: u64, elapsed: Duration) -> f64 {
+    // Counters reset when an interface is recreated
+    let delta = current.checked_sub(previous).unwrap_or(current);
+    delta as f64 / elapsed.as_secs_f64()
+}
+
+/// IPv4 and IPv6 addresses of every interface
+#[cfg(unix)]
+fn interface_addresses() -> Result<HashMap<String, Vec<String>>> {
+    use std::{
+        ffi::CStr,
+        net::{Ipv4Addr, Ipv6Addr},
+    };
+
+    let mut addresses = HashMap::<String, Vec<String>>::new();
+    let mut ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs allocates the list, which is freed below
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut current = ifaddrs;
+    while !current.is_null() {
+        // SAFETY: current is a non-null entry of the list returned by getifaddrs
+        let ifaddr = unsafe { &*current };
+        current = ifaddr.ifa_next;
+        if ifaddr.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_name is a NUL terminated string and ifa_addr is non-null
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_string_lossy();
+        let family = unsafe { (*ifaddr.ifa_addr).sa_family } as i32;
+        let address = match family {
+            libc::AF_INET => {
+                // SAFETY: ifa_addr points to a sockaddr_in for AF_INET
+                let addr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_in) };
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).to_string()
+            }
+            libc::AF_INET6 => {
+                // SAFETY: ifa_addr points to a sockaddr_in6 for AF_INET6
+                let addr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_in6) };
+                Ipv6Addr::from(addr.sin6_addr.s6_addr).to_string()
+            }
+            _ => continue,
+        };
+        addresses
+            .entry(name.into_owned())
+            .or_default()
+            .push(address);
+    }
+    // SAFETY: ifaddrs was allocated by getifaddrs and is no longer referenced
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(addresses)
+}
+
+#[cfg(not(unix))]
+fn interface_addresses() -> Result<HashMap<String, Vec<String>>> {
+    Ok(HashMap::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  123456     100    0    0    0     0          0         0   123456     100    0    0    0     0       0          0
+  eth0: 9876543    5000    1    2    0     0          0        10  1234567    4000    0    0    0     0       0          0
+";
+
+    #[test]
+    fn parse_net_dev_reads_rx_and_tx_bytes() {
+        assert_eq!(
+            parse_net_dev(NET_DEV).unwrap(),
+            [
+                (
+                    "lo".to_string(),
+                    Counters {
+                        rx_bytes: 123456,
+                        tx_bytes: 123456
+                    }
+                ),
+                (
+                    "eth0".to_string(),
+                    Counters {
+                        rx_bytes: 9876543,
+                        tx_bytes: 1234567
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_net_dev_skips_headers_only() {
+        let headers = NET_DEV.lines().take(2).collect::<Vec<_>>().join("\n");
+        assert_eq!(parse_net_dev(&headers).unwrap(), []);
+    }
+
+    #[test]
+    fn parse_net_dev_rejects_malformed_lines() {
+        for line in [
+            "  eth0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16",
+            "  eth0: 1 2 3 4 5 6 7 8",
+            "  eth0: 1 2 3 4 5 6 7 x 9 10 11 12 13 14 15 16",
+        ] {
+            let net_dev = format!("header\nheader\n{line}\n");
+            assert!(
+                matches!(parse_net_dev(&net_dev), Err(Error::Sensor(_))),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn rate_is_bytes_per_second() {
+        assert_eq!(rate(3000, 1000, Duration::from_secs(2)), 1000.0);
+        assert_eq!(rate(1000, 1000, Duration::from_secs(2)), 0.0);
+        assert_eq!(rate(1500, 1000, Duration::from_millis(500)), 1000.0);
+    }
+
+    #[test]
+    fn rate_after_counter_reset_counts_from_zero() {
+        assert_eq!(rate(200, 5000, Duration::from_secs(2)), 100.0);
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::Result;
+
+/// Battery state of charge and AC adapter status from `/sys/class/power_supply`
+pub struct PowerSupplySensor {
+    interval: Duration,
+    path: PathBuf,
+    batteries: Vec<String>,
+}
+
+/// State of one battery
+#[derive(Debug, Clone, PartialEq)]
+pub struct Battery {
+    pub percent: Option<u64>,
+    /// Lowercase status, e.g. `charging`, `discharging` or `full`
+    pub status: String,
+    pub time_to_empty: Option<u64>,
+}
+
+const BATTERY_STATUSES: [&str; 5] = ["charging", "discharging", "full", "not_charging", "unknown"];
+
+impl PowerSupplySensor {
+    pub fn new(interval: Duration, sys_path: &Path) -> Self {
+        let mut sensor = Self {
+            interval,
+            path: sys_path.join("class").join("power_supply"),
+            batteries: Vec::new(),
+        };
+        sensor.batteries = sensor.supplies("Battery").unwrap_or_default();
+        sensor
+    }
+
+    /// Names of power supplies of the given type, e.g. `Battery` or `Mains`
+    fn supplies(&self, supply_type: &str) -> Result<Vec<String>> {
+        let mut supplies = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if read_attribute(&entry.path(), "type").as_deref() == Some(supply_type) {
+                supplies.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        supplies.sort();
+        Ok(supplies)
+    }
+}
+
+impl Sensor for PowerSupplySensor {
+    fn name(&self) -> &str {
+        "power"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let mut readings = Vec::new();
+        let mains = self.supplies("Mains")?;
+        if !mains.is_empty() {
+            let ac_connected = mains.iter().any(|name| {
+                read_attribute(&self.path.join(name), "online").as_deref() == Some("1")
+            });
+            readings.push(Reading::new("power/ac_connected", ac_connected));
+        }
+        self.batteries = self.supplies("Battery")?;
+        for name in &self.batteries {
+            let battery = read_battery(&self.path.join(name));
+            if let Some(percent) = battery.percent {
+                readings.push(Reading::new(format!("power/{name}/percent"), percent));
+            }
+            readings.push(Reading::new(
+                format!("power/{name}/charging"),
+                battery.status == "charging",
+            ));
+            readings.push(Reading::new(
+                format!("power/{name}/status"),
+                &battery.status,
+            ));
+            // Empty when unknown, which also clears the retained value
+            readings.push(Reading::new(
+                format!("power/{name}/time_to_empty_seconds"),
+                battery
+                    .time_to_empty
+                    .map(|seconds| seconds.to_string())
+                    .unwrap_or_default(),
+            ));
+        }
+        Ok(readings)
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        let mut metrics = vec![
+            Metric::new("power/ac_connected", "AC connected", Datatype::Boolean)
+                .device_class("plug"),
+        ];
+        for name in &self.batteries {
+            let id = |metric| format!("power/{name}/{metric}");
+            metrics.extend([
+                Metric::new(id("percent"), format!("{name} charge"), Datatype::Integer)
+                    .unit("%")
+                    .device_class("battery"),
+                Metric::new(
+                    id("charging"),
+                    format!("{name} charging"),
+                    Datatype::Boolean,
+                )
+                .device_class("battery_charging"),
+                Metric::new(
+                    id("status"),
+                    format!("{name} status"),
+                    Datatype::Enum(BATTERY_STATUSES.map(String::from).to_vec()),
+                ),
+                Metric::new(
+                    id("time_to_empty_seconds"),
+                    format!("{name} time to empty"),
+                    Datatype::Integer,
+                )
+                .unit("s")
+                .device_class("duration"),
+            ]);
+        }
+        metrics
+    }
+}
+
+/// Read state of the battery in the given power supply directory
+pub fn read_battery(path: &Path) -> Battery {
+    let number = |attribute| read_attribute(path, attribute).and_then(|v| v.parse::<u64>().ok());
+    let status = read_attribute(path, "status")
+        .map(|status| status.to_ascii_lowercase().replace(' ', "_"))
+        .filter(|status| BATTERY_STATUSES.contains(&status.as_str()))
+        .unwrap_or_else(|| "unknown".into());
+    let time_to_empty = match status.as_str() {
+        "discharging" => number("time_to_empty_now").or_else(|| {
+            // Energy in µWh and power in µW, or charge in µAh and current in µA
+            let (remaining, rate) = match (number("energy_now"), number("power_now")) {
+                (Some(energy), Some(power)) => (energy, power),
+                _ => (number("charge_now")?, number("current_now")?),
+            };
+            (rate > 0).then(|| remaining * 3600 / rate)
+        }),
+        _ => None,
+    };
+    Battery {
+        percent: number("capacity"),
+        status,
+        time_to_empty,
+    }
+}
+
+fn read_attribute(path: &Path, attribute: &str) -> Option<String> {
+    fs::read_to_string(path.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Power supply directory unique to the test, removed when dropped
+    struct Supply(PathBuf);
+
+    impl Supply {
+        fn new(name: &str, attributes: &[(&str, &str)]) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("modo-test-{}-{name}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            for (attribute, value) in attributes {
+                fs::write(path.join(attribute), format!("{value}\n")).unwrap();
+            }
+            Self(path)
+        }
+    }
+
+    impl Drop for Supply {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn energy_based_battery() {
+        let supply = Supply::new(
+            "battery-energy",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("capacity", "80"),
+                ("energy_now", "40000000"),
+                ("power_now", "10000000"),
+            ],
+        );
+        assert_eq!(
+            read_battery(&supply.0),
+            Battery {
+                percent: Some(80),
+                status: "discharging".into(),
+                time_to_empty: Some(4 * 3600),
+            }
+        );
+    }
+
+    #[test]
+    fn charge_based_battery() {
+        let supply = Supply::new(
+            "battery-charge",
+            &[
+                ("status", "Discharging"),
+                ("capacity", "50"),
+                ("charge_now", "2000000"),
+                ("current_now", "4000000"),
+            ],
+        );
+        assert_eq!(read_battery(&supply.0).time_to_empty, Some(1800));
+    }
+
+    #[test]
+    fn time_to_empty_now_is_preferred() {
+        let supply = Supply::new(
+            "battery-time",
+            &[
+                ("status", "Discharging"),
+                ("time_to_empty_now", "600"),
+                ("energy_now", "40000000"),
+                ("power_now", "10000000"),
+            ],
+        );
+        assert_eq!(read_battery(&supply.0).time_to_empty, Some(600));
+    }
+
+    #[test]
+    fn time_to_empty_unknown() {
+        let supply = Supply::new(
+            "battery-unknown",
+            &[("status", "Discharging"), ("energy_now", "40000000")],
+        );
+        let battery = read_battery(&supply.0);
+        assert_eq!(battery.time_to_empty, None);
+        assert_eq!(battery.percent, None);
+
+        let supply = Supply::new(
+            "battery-idle",
+            &[
+                ("status", "Discharging"),
+                ("energy_now", "40000000"),
+                ("power_now", "0"),
+            ],
+        );
+        assert_eq!(read_battery(&supply.0).time_to_empty, None);
+
+        let supply = Supply::new(
+            "battery-charging",
+            &[("status", "Charging"), ("time_to_empty_now", "600")],
+        );
+        assert_eq!(read_battery(&supply.0).time_to_empty, None);
+    }
+
+    #[test]
+    fn status_is_normalized() {
+        let supply = Supply::new("battery-not-charging", &[("status", "Not charging")]);
+        assert_eq!(read_battery(&supply.0).status, "not_charging");
+        let supply = Supply::new("battery-odd-status", &[("status", "Exploding")]);
+        assert_eq!(read_battery(&supply.0).status, "unknown");
+    }
+
+    #[test]
+    fn unknown_time_to_empty_is_published_empty() {
+        let supply = Supply::new("power-supply", &[]);
+        let battery = supply.0.join("BAT0");
+        fs::create_dir_all(&battery).unwrap();
+        for (attribute, value) in [("type", "Battery"), ("status", "Full"), ("capacity", "100")] {
+            fs::write(battery.join(attribute), value).unwrap();
+        }
+        let mut sensor = PowerSupplySensor {
+            interval: Duration::from_secs(1),
+            path: supply.0.clone(),
+            batteries: Vec::new(),
+        };
+        let readings = sensor.read().unwrap();
+        assert!(readings.contains(&Reading::new("power/BAT0/time_to_empty_seconds", "")));
+        assert!(readings.contains(&Reading::new("power/BAT0/percent", 100)));
+    }
+}
@@ -0,0 +1,68 @@
+use std::{path::PathBuf, time::Duration};
+
+use super::{Datatype, Metric, Reading, Sensor};
+use crate::{Error, Result};
+
+/// System uptime from `/proc/uptime`
+pub struct UptimeSensor {
+    interval: Duration,
+    path: PathBuf,
+}
+
+impl UptimeSensor {
+    pub fn new(interval: Duration, proc_path: PathBuf) -> Self {
+        Self {
+            interval,
+            path: proc_path.join("uptime"),
+        }
+    }
+}
+
+impl Sensor for UptimeSensor {
+    fn name(&self) -> &str {
+        "uptime"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn read(&mut self) -> Result<Vec<Reading>> {
+        let uptime = parse_uptime(&std::fs::read_to_string(&self.path)?)?;
+        Ok(vec![Reading::new("uptime_seconds", uptime)])
+    }
+
+    fn metrics(&self) -> Vec<Metric> {
+        vec![
+            Metric::new("uptime_seconds", "Uptime", Datatype::Integer)
+                .unit("s")
+                .device_class("duration"),
+        ]
+    }
+}
+
+/// Parse whole seconds since boot
+pub fn parse_uptime(uptime: &str) -> Result<u64> {
+    uptime
+        .split_whitespace()
+        .next()
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .map(|seconds| seconds as u64)
+        .ok_or_else(|| Error::Sensor(format!("invalid /proc/uptime {uptime:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_whole_seconds() {
+        assert_eq!(parse_uptime("350735.47 234388.90\n").unwrap(), 350735);
+    }
+
+    #[test]
+    fn rejects_invalid_uptime() {
+        assert!(matches!(parse_uptime(""), Err(Error::Sensor(_))));
+        assert!(matches!(parse_uptime("soon 1.0\n"), Err(Error::Sensor(_))));
+    }
+}
@@ -0,0 +1,38 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+/// Shared flag telling tasks to stop, which also interrupts their sleep
+#[derive(Clone)]
+pub struct Shutdown {
+    triggered: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            triggered: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Ask all tasks to stop
+    pub fn trigger(&self) {
+        self.triggered.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Sleep for the given duration, returns true if woken up by a shutdown
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        let mut triggered = self.triggered.subscribe();
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = triggered.wait_for(|triggered| *triggered) => {}
+        }
+        self.is_triggered()
+    }
+}
@@ -0,0 +1,78 @@
+//! Consolidated snapshot of all sensor values on `<root>/<hostname>/state`
+//!
+//! Every time readings are published the snapshot is updated and published
+//! as one JSON object, keyed by metric id, next to a `timestamp`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{SubsecRound, Utc};
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+
+use crate::sensor::{Datatype, Reading, SensorInfo};
+
+pub const STATE_TOPIC: &str = "state";
+
+/// Latest published value of every metric
+pub struct State {
+    datatypes: HashMap<String, Datatype>,
+    values: Mutex<BTreeMap<String, Value>>,
+}
+
+impl State {
+    pub fn new(sensors: &[SensorInfo]) -> Self {
+        let datatypes = sensors
+            .iter()
+            .flat_map(|sensor| &sensor.metrics)
+            .map(|metric| (metric.id.clone(), metric.datatype.clone()))
+            .collect();
+        Self {
+            datatypes,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Remember readings and serialize the snapshot
+    ///
+    /// The snapshot is published by `publish` with the lock held, so snapshots
+    /// from different sensor tasks are published in order.
+    pub async fn update<F, P>(&self, readings: &[Reading], publish: F)
+    where
+        F: FnOnce(Vec<u8>) -> P,
+        P: Future<Output = ()>,
+    {
+        if readings.is_empty() {
+            return;
+        }
+        let mut values = self.values.lock().await;
+        for reading in readings {
+            values.insert(reading.metric.clone(), self.value(reading));
+        }
+        let mut snapshot = values
+            .iter()
+            .map(|(metric, value)| (metric.clone(), value.clone()))
+            .collect::<Map<_, _>>();
+        snapshot.insert(
+            "timestamp".into(),
+            Utc::now().trunc_subsecs(0).to_rfc3339().into(),
+        );
+        match serde_json::to_vec(&snapshot) {
+            Ok(payload) => publish(payload).await,
+            Err(e) => eprintln!("state_error={e}"),
+        }
+    }
+
+    /// JSON value of a reading according to the datatype of its metric
+    fn value(&self, reading: &Reading) -> Value {
+        let value = reading.value.as_str();
+        let parsed = match self.datatypes.get(&reading.metric) {
+            // Numbers are published empty when unknown
+            Some(Datatype::Integer | Datatype::Float) if value.is_empty() => Some(Value::Null),
+            Some(Datatype::Integer) => value.parse::<i64>().ok().map(Value::from),
+            Some(Datatype::Float) => value.parse::<f64>().ok().map(Value::from),
+            Some(Datatype::Boolean) => value.parse::<bool>().ok().map(Value::from),
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| value.into())
+    }
+}